@@ -1,8 +1,8 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault, Promise};
-use escrow::{verify_secret, Immutables, Stage, TimeBoundKind, DataKey};
+use near_sdk::{env, Gas, near_bindgen, AccountId, PanicOnDefault, Promise};
+use escrow::{verify_secret, Immutables, Stage, TimeBoundKind};
 
-const GAS_FOR_FT_TRANSFER: u64 = 5_000_000_000_000;
+const GAS_FOR_FT_TRANSFER: Gas = Gas(5_000_000_000_000);
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -17,7 +17,7 @@ impl EscrowDst {
         Self { factory }
     }
 
-    pub fn withdraw(&mut self, secret: String, immutables: Immutables) {
+    pub fn withdraw(&mut self, secret: String, immutables: Immutables) -> Promise {
         let taker = env::predecessor_account_id();
         assert_eq!(immutables.taker, taker, "Only taker can withdraw");
         
@@ -30,10 +30,10 @@ impl EscrowDst {
             "Too late"
         );
         
-        self._withdraw(secret, immutables);
+        self._withdraw(secret, immutables)
     }
 
-    pub fn public_withdraw(&mut self, secret: String, immutables: Immutables) {
+    pub fn public_withdraw(&mut self, secret: String, immutables: Immutables) -> Promise {
         assert!(
             !immutables.timelocks.is_stage_time(Stage::DstPublicWithdrawal, TimeBoundKind::Before),
             "Too early"
@@ -43,10 +43,10 @@ impl EscrowDst {
             "Too late"
         );
         
-        self._withdraw(secret, immutables);
+        self._withdraw(secret, immutables)
     }
 
-    pub fn cancel(&mut self, immutables: Immutables) {
+    pub fn cancel(&mut self, immutables: Immutables) -> Promise {
         let taker = env::predecessor_account_id();
         assert_eq!(immutables.taker, taker, "Only taker can cancel");
         
@@ -55,21 +55,23 @@ impl EscrowDst {
             "Too early"
         );
         
-        self._transfer_to(&immutables.token, &immutables.taker, immutables.amount.0);
+        self._transfer_to(&immutables.token, &immutables.taker, immutables.amount.0)
     }
 
-    fn _withdraw(&mut self, secret: String, immutables: Immutables) {
+    fn _withdraw(&mut self, secret: String, immutables: Immutables) -> Promise {
         assert!(
             verify_secret(&secret, &immutables.hashlock),
             "Invalid secret"
         );
         
-        self._transfer_to(&immutables.token, &immutables.maker, immutables.amount.0);
+        self._transfer_to(&immutables.token, &immutables.maker, immutables.amount.0)
     }
 
-    fn _transfer_to(&self, token: &AccountId, recipient: &AccountId, amount: u128) {
+    /// Returned to the caller so its callback sees the result of the transfer
+    /// rather than of the escrow method.
+    fn _transfer_to(&self, token: &AccountId, recipient: &AccountId, amount: u128) -> Promise {
         Promise::new(token.clone()).function_call(
-            "ft_transfer".as_bytes().to_vec(),
+            "ft_transfer".to_string(),
             format!(
                 r#"{{"receiver_id":"{}","amount":"{}","memo":"Escrow transfer"}}"#,
                 recipient, amount
@@ -78,6 +80,6 @@ impl EscrowDst {
             .to_vec(),
             1,
             GAS_FOR_FT_TRANSFER,
-        );
+        )
     }
 }
\ No newline at end of file
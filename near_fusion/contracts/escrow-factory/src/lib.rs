@@ -1,14 +1,13 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::UnorderedMap;
-use near_sdk::json_types::U128;
-use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault, Promise, Balance};
-use escrow::{Immutables, Stage, DataKey};
+use near_sdk::{env, Gas, near_bindgen, AccountId, PanicOnDefault, Promise, Balance};
+use escrow::{Immutables, Stage};
 
 #[cfg(test)]
 mod test;
 
-const GAS_FOR_FT_TRANSFER: u64 = 10_000_000_000_000;
-const GAS_FOR_DEPLOY: u64 = 50_000_000_000_000;
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_DEPLOY: Gas = Gas(50_000_000_000_000);
 const ESCROW_DEPOSIT: Balance = 5_000_000_000_000_000_000_000_000;
 
 #[near_bindgen]
@@ -46,14 +45,14 @@ impl EscrowFactory {
             .transfer(ESCROW_DEPOSIT)
             .deploy_contract(self.escrow_src_code.clone())
             .function_call(
-                "new".as_bytes().to_vec(),
+                "new".to_string(),
                 format!(r#"{{"factory":"{}"}}"#, factory).as_bytes().to_vec(),
                 0,
                 GAS_FOR_DEPLOY,
             );
         
         Promise::new(immutables.token.clone()).function_call(
-            "ft_transfer_from".as_bytes().to_vec(),
+            "ft_transfer_from".to_string(),
             format!(
                 r#"{{"sender_id":"{}","receiver_id":"{}","amount":"{}","memo":"Escrow deposit"}}"#,
                 immutables.maker,
@@ -94,14 +93,14 @@ impl EscrowFactory {
             .transfer(ESCROW_DEPOSIT)
             .deploy_contract(self.escrow_dst_code.clone())
             .function_call(
-                "new".as_bytes().to_vec(),
+                "new".to_string(),
                 format!(r#"{{"factory":"{}"}}"#, factory).as_bytes().to_vec(),
                 0,
                 GAS_FOR_DEPLOY,
             );
         
         Promise::new(immutables.token.clone()).function_call(
-            "ft_transfer".as_bytes().to_vec(),
+            "ft_transfer".to_string(),
             format!(
                 r#"{{"receiver_id":"{}","amount":"{}","memo":"Escrow deposit"}}"#,
                 escrow_account_id,
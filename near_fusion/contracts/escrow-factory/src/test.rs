@@ -1,9 +1,10 @@
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use crate::*;
     use near_sdk::test_utils::{accounts, VMContextBuilder};
     use near_sdk::{testing_env, VMContext, Balance};
     use escrow::{Immutables, Timelocks};
+    use near_sdk::json_types::U128;
 
     const NEAR: Balance = 1_000_000_000_000_000_000_000_000;
 
@@ -52,7 +53,7 @@ mod tests {
 
     #[test]
     fn test_create_src_escrow() {
-        let mut context = get_context(accounts(0));
+        let context = get_context(accounts(0));
         testing_env!(context);
         
         let src_code = vec![1, 2, 3];
@@ -75,7 +76,7 @@ mod tests {
 
     #[test]
     fn test_create_dst_escrow() {
-        let mut context = get_context(accounts(2)); // Taker account
+        let context = get_context(accounts(2)); // Taker account
         testing_env!(context);
         
         let src_code = vec![1, 2, 3];
@@ -100,7 +101,7 @@ mod tests {
     #[test]
     #[should_panic(expected = "Only taker can create dst escrow")]
     fn test_create_dst_escrow_wrong_caller() {
-        let mut context = get_context(accounts(1)); // Maker account (wrong)
+        let context = get_context(accounts(1)); // Maker account (wrong)
         testing_env!(context);
         
         let src_code = vec![1, 2, 3];
@@ -115,7 +116,7 @@ mod tests {
     #[test]
     #[should_panic(expected = "Invalid creation time")]
     fn test_create_dst_escrow_invalid_time() {
-        let mut context = get_context(accounts(2)); // Taker account
+        let context = get_context(accounts(2)); // Taker account
         testing_env!(context);
         
         let src_code = vec![1, 2, 3];
@@ -132,7 +133,7 @@ mod tests {
     #[test]
     #[should_panic(expected = "Escrow already deployed")]
     fn test_duplicate_deployment() {
-        let mut context = get_context(accounts(0));
+        let context = get_context(accounts(0));
         testing_env!(context);
         
         let src_code = vec![1, 2, 3];
@@ -1,8 +1,8 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault, Promise};
-use escrow::{verify_secret, Immutables, Stage, TimeBoundKind, DataKey};
+use near_sdk::{env, Gas, near_bindgen, AccountId, PanicOnDefault, Promise};
+use escrow::{verify_secret, Immutables, Stage, TimeBoundKind};
 
-const GAS_FOR_FT_TRANSFER: u64 = 5_000_000_000_000;
+const GAS_FOR_FT_TRANSFER: Gas = Gas(5_000_000_000_000);
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -17,7 +17,7 @@ impl EscrowSrc {
         Self { factory }
     }
 
-    pub fn withdraw(&mut self, secret: String, immutables: Immutables) {
+    pub fn withdraw(&mut self, secret: String, immutables: Immutables) -> Promise {
         let taker = env::predecessor_account_id();
         assert_eq!(immutables.taker, taker, "Only taker can withdraw");
         
@@ -30,10 +30,10 @@ impl EscrowSrc {
             "Too late"
         );
         
-        self._withdraw_to(secret, immutables.taker.clone(), immutables);
+        self._withdraw_to(secret, immutables.taker.clone(), immutables)
     }
 
-    pub fn withdraw_to(&mut self, secret: String, target: AccountId, immutables: Immutables) {
+    pub fn withdraw_to(&mut self, secret: String, target: AccountId, immutables: Immutables) -> Promise {
         let taker = env::predecessor_account_id();
         assert_eq!(immutables.taker, taker, "Only taker can withdraw");
         
@@ -46,10 +46,10 @@ impl EscrowSrc {
             "Too late"
         );
         
-        self._withdraw_to(secret, target, immutables);
+        self._withdraw_to(secret, target, immutables)
     }
 
-    pub fn public_withdraw(&mut self, secret: String, immutables: Immutables) {
+    pub fn public_withdraw(&mut self, secret: String, immutables: Immutables) -> Promise {
         assert!(
             !immutables.timelocks.is_stage_time(Stage::SrcPublicWithdrawal, TimeBoundKind::Before),
             "Too early"
@@ -59,10 +59,10 @@ impl EscrowSrc {
             "Too late"
         );
         
-        self._withdraw_to(secret, immutables.taker.clone(), immutables);
+        self._withdraw_to(secret, immutables.taker.clone(), immutables)
     }
 
-    pub fn cancel(&mut self, immutables: Immutables) {
+    pub fn cancel(&mut self, immutables: Immutables) -> Promise {
         let taker = env::predecessor_account_id();
         assert_eq!(immutables.taker, taker, "Only taker can cancel");
         
@@ -71,30 +71,32 @@ impl EscrowSrc {
             "Too early"
         );
         
-        self._transfer_to(&immutables.token, &immutables.maker, immutables.amount.0);
+        self._transfer_to(&immutables.token, &immutables.maker, immutables.amount.0)
     }
 
-    pub fn public_cancel(&mut self, immutables: Immutables) {
+    pub fn public_cancel(&mut self, immutables: Immutables) -> Promise {
         assert!(
             !immutables.timelocks.is_stage_time(Stage::SrcPublicCancellation, TimeBoundKind::Before),
             "Too early"
         );
         
-        self._transfer_to(&immutables.token, &immutables.maker, immutables.amount.0);
+        self._transfer_to(&immutables.token, &immutables.maker, immutables.amount.0)
     }
 
-    fn _withdraw_to(&mut self, secret: String, target: AccountId, immutables: Immutables) {
+    fn _withdraw_to(&mut self, secret: String, target: AccountId, immutables: Immutables) -> Promise {
         assert!(
             verify_secret(&secret, &immutables.hashlock),
             "Invalid secret"
         );
         
-        self._transfer_to(&immutables.token, &target, immutables.amount.0);
+        self._transfer_to(&immutables.token, &target, immutables.amount.0)
     }
 
-    fn _transfer_to(&self, token: &AccountId, recipient: &AccountId, amount: u128) {
+    /// Returned to the caller so its callback sees the result of the transfer
+    /// rather than of the escrow method.
+    fn _transfer_to(&self, token: &AccountId, recipient: &AccountId, amount: u128) -> Promise {
         Promise::new(token.clone()).function_call(
-            "ft_transfer".as_bytes().to_vec(),
+            "ft_transfer".to_string(),
             format!(
                 r#"{{"receiver_id":"{}","amount":"{}","memo":"Escrow transfer"}}"#,
                 recipient, amount
@@ -103,6 +105,6 @@ impl EscrowSrc {
             .to_vec(),
             1,
             GAS_FOR_FT_TRANSFER,
-        );
+        )
     }
 }
\ No newline at end of file
@@ -0,0 +1,71 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, AccountId};
+
+pub const MAX_BIDS: usize = 20;
+
+/// Phases of a sealed-bid round, all in block timestamp units. Commits are
+/// taken until `commit_end`, reveals until `reveal_end`, and the winner then
+/// has `fill_window` to fill the order alone.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BiddingSchedule {
+    pub commit_end: u64,
+    pub reveal_end: u64,
+    pub fill_window: u64,
+    /// NEAR each bidder locks with their commitment.
+    pub bond: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SealedBid {
+    pub resolver: AccountId,
+    pub commitment: [u8; 32],
+    /// Auction bump (basis points) the resolver commits to pay, once revealed.
+    pub rate_bump: Option<u32>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BiddingRound {
+    pub schedule: BiddingSchedule,
+    /// Making amount the winner is expected to fill.
+    pub making_amount: U128,
+    pub bids: Vec<SealedBid>,
+    /// Index into `bids` of the best revealed bid.
+    pub winner: Option<usize>,
+    pub winner_filled: U128,
+    pub is_settled: bool,
+}
+
+impl BiddingRound {
+    /// Fills are held back until the round has picked a winner.
+    pub fn is_open(&self, current_time: u64) -> bool {
+        !self.is_settled && current_time < self.schedule.reveal_end
+    }
+
+    pub fn window_end(&self) -> u64 {
+        self.schedule.reveal_end + self.schedule.fill_window
+    }
+
+    /// The winning bid while its exclusive fill window runs.
+    pub fn exclusive_bid(&self, current_time: u64) -> Option<&SealedBid> {
+        if self.is_settled || current_time < self.schedule.reveal_end || current_time >= self.window_end() {
+            return None;
+        }
+        self.winner.map(|index| &self.bids[index])
+    }
+
+    pub fn winner_filled_in_full(&self) -> bool {
+        self.winner.is_some() && self.winner_filled.0 >= self.making_amount.0
+    }
+}
+
+/// Hash a resolver commits to; binding the account stops others copying a bid.
+pub fn commitment(resolver: &AccountId, rate_bump: u32, salt: &str) -> [u8; 32] {
+    env::sha256(format!("{}:{}:{}", resolver, rate_bump, salt).as_bytes())
+        .try_into()
+        .unwrap()
+}
\ No newline at end of file
@@ -1,14 +1,38 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::UnorderedMap;
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet};
 use near_sdk::json_types::U128;
-use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault, Promise};
-use escrow::{FusionOrder, AuctionDetails, ResolverFee};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, Gas, near_bindgen, AccountId, PanicOnDefault, Promise, PromiseOrValue, PromiseResult};
+use escrow::{mul_div, FusionOrder, AuctionDetails, ResolverFee};
 
+pub use bidding::{BiddingRound, BiddingSchedule, SealedBid};
+
+mod bidding;
+mod predicate;
 #[cfg(test)]
 mod tests;
 
-const GAS_FOR_FT_TRANSFER: u64 = 10_000_000_000_000;
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_CALLBACK: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_FILL_CALLBACK: Gas = Gas(30_000_000_000_000);
+const GAS_FOR_PREDICATE_CALLBACK: Gas = Gas(60_000_000_000_000);
 const BASIS_POINTS: u32 = 10_000;
+const MAX_PROTOCOL_FEE_BASIS_POINTS: u32 = 500;
+const MAX_INTEGRATOR_FEE_BASIS_POINTS: u32 = 300;
+/// Share of an order's storage deposit paid to whoever prunes it.
+const PRUNE_TIP_BASIS_POINTS: u32 = 1_000;
+/// Delay before a scheduled protocol fee change applies (24h, block timestamp units).
+const PROTOCOL_FEE_NOTICE_PERIOD: u64 = 86_400_000_000_000;
+/// Fills kept per order; older entries are dropped first.
+const MAX_FILL_HISTORY: usize = 32;
+const MAX_AMENDMENTS: usize = 8;
+/// Bytes reserved per fill history entry and per sealed bid. Both grow after
+/// the maker has paid, so their room is paid for up front at its bound.
+const FILL_RECORD_STORAGE: u64 = 250;
+const SEALED_BID_STORAGE: u64 = 150;
+const MAX_TWAP_SLICES: u32 = 100;
+const DEFAULT_PAGE_LIMIT: u64 = 50;
+const MAX_PAGE_LIMIT: u64 = 100;
 
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct OrderState {
@@ -17,187 +41,2226 @@ pub struct OrderState {
     pub filled_taking_amount: u128,
     pub is_cancelled: bool,
     pub auction: Option<AuctionDetails>,
+    pub created_at: u64,
+    /// Fills whose payouts have not settled yet.
+    pub fills_in_flight: u32,
+    /// Paid by the maker to cover this order's storage, returned on `prune_order`.
+    pub storage_deposit: u128,
+    pub amendments: Vec<OrderAmendment>,
+    /// Terms set by the latest amendment; the signed order itself never changes.
+    pub amended_terms: Option<OrderTerms>,
+    /// Final outcome, set once the order is cancelled or completely filled.
+    pub settlement: Option<OrderSettlement>,
+    pub twap: Option<TwapSchedule>,
+    pub recurring: Option<RecurringSchedule>,
+    /// Recurring orders only: the period last filled in and how much it used.
+    pub current_period: u64,
+    pub current_period_filled: u128,
+}
+
+impl OrderState {
+    /// Terms fills are sized and priced on: the signed ones until amended.
+    pub fn terms(&self) -> OrderTerms {
+        self.amended_terms.clone().unwrap_or(OrderTerms {
+            making_amount: self.order.making_amount,
+            taking_amount: self.order.taking_amount,
+            expiry: self.order.expiry,
+            filled_making_base: U128(0),
+            filled_taking_base: U128(0),
+        })
+    }
+
+    /// The signed order with the terms in force applied, for pricing fills.
+    pub fn effective_order(&self) -> FusionOrder {
+        let terms = self.terms();
+        FusionOrder {
+            making_amount: terms.making_amount,
+            taking_amount: terms.taking_amount,
+            expiry: terms.expiry,
+            ..self.order.clone()
+        }
+    }
+
+    /// Filled amounts counted against the terms in force.
+    pub fn terms_filled_amounts(&self) -> (u128, u128) {
+        let terms = self.terms();
+        (
+            self.filled_making_amount - terms.filled_making_base.0,
+            self.filled_taking_amount - terms.filled_taking_base.0,
+        )
+    }
+
+    pub fn remaining_making_amount(&self) -> u128 {
+        self.terms().making_amount.0 - self.terms_filled_amounts().0
+    }
+
+    pub fn remaining_taking_amount(&self) -> u128 {
+        self.terms().taking_amount.0 - self.terms_filled_amounts().1
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.is_cancelled && self.remaining_making_amount() > 0
+    }
+
+    /// Expired orders stay active, and listed, until cancelled or pruned.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.terms().expiry.is_some_and(|expiry| now >= expiry)
+    }
+
+    /// Making amount released for filling so far; only TWAP orders hold some back.
+    pub fn unlocked_making_amount(&self, current_time: u64) -> u128 {
+        match &self.twap {
+            Some(twap) => twap.unlocked_amount(self.order.making_amount.0, current_time),
+            None => self.order.making_amount.0,
+        }
+    }
+
+    /// Making amount a fill could take right now, after TWAP unlocks or the
+    /// recurring per-period budget.
+    pub fn fillable_making_amount(&self, current_time: u64) -> u128 {
+        let remaining = self.remaining_making_amount();
+        if let Some(recurring) = &self.recurring {
+            if current_time < recurring.start_time {
+                return 0;
+            }
+            let used = if recurring.period_index(current_time) == self.current_period {
+                self.current_period_filled
+            } else {
+                0
+            };
+            return recurring.amount_per_period.0.saturating_sub(used).min(remaining);
+        }
+        self.unlocked_making_amount(current_time)
+            .saturating_sub(self.filled_making_amount)
+            .min(remaining)
+    }
+
+    pub fn rate_bump(&self, current_time: u64) -> u32 {
+        self.auction
+            .as_ref()
+            .map(|auction| match (&self.twap, &self.recurring) {
+                (Some(twap), _) => twap
+                    .slice_auction(auction, twap.current_slice(current_time))
+                    .get_rate_bump(current_time),
+                (_, Some(recurring)) => recurring
+                    .period_auction(auction, recurring.period_index(current_time))
+                    .get_rate_bump(current_time),
+                _ => auction.get_rate_bump(current_time),
+            })
+            .unwrap_or(0)
+    }
+}
+
+/// Releases an order's making amount in `slice_count` equal slices, one every
+/// `interval` from `start_time`. Each slice reruns the order's auction from its
+/// own unlock time; unfilled slices carry over at the latest slice's price.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TwapSchedule {
+    pub start_time: u64,
+    pub slice_count: u32,
+    pub interval: u64,
+}
+
+impl TwapSchedule {
+    /// Checks the shape of the schedule and that every slice's unlock and auction
+    /// start fit in a timestamp, so the other methods cannot overflow afterwards.
+    pub fn is_valid(&self, auction: Option<&AuctionDetails>) -> bool {
+        if self.slice_count < 2 || self.slice_count > MAX_TWAP_SLICES || self.interval == 0 {
+            return false;
+        }
+        self.slice_offset(self.slice_count - 1).is_some_and(|last_offset| {
+            self.start_time.checked_add(last_offset).is_some()
+                && auction.map_or(true, |auction| auction.start_time.checked_add(last_offset).is_some())
+        })
+    }
+
+    fn slice_offset(&self, slice: u32) -> Option<u64> {
+        (slice as u64).checked_mul(self.interval)
+    }
+
+    pub fn unlock_time(&self, slice: u32) -> u64 {
+        self.slice_offset(slice)
+            .and_then(|offset| self.start_time.checked_add(offset))
+            .expect("TWAP schedule overflow")
+    }
+
+    /// Latest slice unlocked at `current_time`, or the first one if none is yet.
+    pub fn current_slice(&self, current_time: u64) -> u32 {
+        if current_time < self.start_time {
+            return 0;
+        }
+        let elapsed_slices = (current_time - self.start_time) / self.interval;
+        elapsed_slices.min(self.slice_count as u64 - 1) as u32
+    }
+
+    pub fn unlocked_amount(&self, total: u128, current_time: u64) -> u128 {
+        if current_time < self.start_time {
+            return 0;
+        }
+        let unlocked_slices = self.current_slice(current_time) + 1;
+        mul_div(total, unlocked_slices as u128, self.slice_count as u128, false)
+    }
+
+    pub fn slice_auction(&self, auction: &AuctionDetails, slice: u32) -> AuctionDetails {
+        AuctionDetails {
+            start_time: self
+                .slice_offset(slice)
+                .and_then(|offset| auction.start_time.checked_add(offset))
+                .expect("TWAP schedule overflow"),
+            ..auction.clone()
+        }
+    }
+}
+
+/// Spends up to `amount_per_period` of the making amount in every `period`
+/// from `start_time` until the order runs out or is cancelled. Each period
+/// reruns the order's auction from its start; unspent budget does not carry over.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecurringSchedule {
+    pub start_time: u64,
+    pub period: u64,
+    pub amount_per_period: U128,
+}
+
+impl RecurringSchedule {
+    pub fn period_index(&self, current_time: u64) -> u64 {
+        current_time.saturating_sub(self.start_time) / self.period
+    }
+
+    /// Periods run indefinitely, so a start past the end of time saturates to
+    /// `u64::MAX` (a period that never begins) rather than overflowing.
+    pub fn period_start(&self, period_index: u64) -> u64 {
+        self.start_time.saturating_add(period_index.saturating_mul(self.period))
+    }
+
+    pub fn period_auction(&self, auction: &AuctionDetails, period_index: u64) -> AuctionDetails {
+        AuctionDetails {
+            start_time: auction.start_time.saturating_add(period_index.saturating_mul(self.period)),
+            ..auction.clone()
+        }
+    }
+}
+
+/// Where a recurring order stands in its current period.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecurringPeriod {
+    pub period_index: u64,
+    pub period_start: u64,
+    pub period_end: u64,
+    pub available_making_amount: U128,
+    pub auction: Option<AuctionDetails>,
+}
+
+/// One entry of a TWAP order's schedule, as returned by `get_twap_schedule`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TwapSlice {
+    pub index: u32,
+    pub unlock_time: u64,
+    pub making_amount: U128,
+    pub auction: Option<AuctionDetails>,
+    pub is_unlocked: bool,
+}
+
+/// Size, price and expiry an order is filled on after `amend_order`. The
+/// amounts cover what was unfilled when the terms were set.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderTerms {
+    pub making_amount: U128,
+    pub taking_amount: U128,
+    pub expiry: Option<u64>,
+    /// Order fill totals when the terms were set; later fills count against them.
+    pub filled_making_base: U128,
+    pub filled_taking_base: U128,
+}
+
+/// One `amend_order` call. Amounts are what remained before and after it.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderAmendment {
+    pub previous_making_amount: U128,
+    pub previous_taking_amount: U128,
+    pub making_amount: U128,
+    pub taking_amount: U128,
+    pub auction_duration: Option<u64>,
+    pub expiry: Option<u64>,
+    pub amended_at: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum SettlementReason {
+    Filled,
+    Cancelled,
+}
+
+/// What an order ended up doing, kept for the frontend once it is closed.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderSettlement {
+    pub reason: SettlementReason,
+    pub filled_making_amount: U128,
+    pub filled_taking_amount: U128,
+    /// Unfilled making amount returned to the maker's balance on cancellation.
+    pub released_making_amount: U128,
+    pub settled_at: u64,
+}
+
+/// How the taker sized a fill.
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum FillAmount {
+    /// Both sides given by the taker; they must match the order ratio exactly.
+    Exact(U128, U128),
+    /// Making amount given; taking amount derived and rounded up.
+    Making(U128),
+    /// Taking amount given; making amount derived and rounded down.
+    Taking(U128),
+}
+
+/// Taker-side bounds checked against the final price of a fill, after the
+/// auction bump, resolver fee and protocol fees have been applied.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FillLimits {
+    /// Most `taker_asset` the taker will pay, bump and resolver fee included.
+    pub max_taking_amount: Option<U128>,
+    /// Least `maker_asset` the taker must receive after fees.
+    pub min_making_amount: Option<U128>,
+    /// Block timestamp after which the fill must not execute.
+    pub deadline: Option<u64>,
+}
+
+/// A taker's fill as it travels through the predicate and settlement steps.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FillRequest {
+    pub fill: FillAmount,
+    pub resolver_fee: Option<ResolverFee>,
+    pub limits: FillLimits,
+}
+
+/// `msg` payload accepted by `ft_on_transfer`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", tag = "action", rename_all = "snake_case")]
+pub enum TransferMessage {
+    Deposit,
+    Fill {
+        order_hash: [u8; 32],
+        making_amount: U128,
+        taking_amount: U128,
+        resolver_fee: Option<ResolverFee>,
+        #[serde(default)]
+        limits: FillLimits,
+    },
+    FillByMaking {
+        order_hash: [u8; 32],
+        making_amount: U128,
+        resolver_fee: Option<ResolverFee>,
+        #[serde(default)]
+        limits: FillLimits,
+    },
+    FillByTaking {
+        order_hash: [u8; 32],
+        taking_amount: U128,
+        resolver_fee: Option<ResolverFee>,
+        #[serde(default)]
+        limits: FillLimits,
+    },
+}
+
+/// Everything a fill changed, so the settlement callback can undo it.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FillReceipt {
+    pub order_hash: [u8; 32],
+    pub taker: AccountId,
+    pub making_amount: U128,
+    pub taking_amount: U128,
+    pub actual_taking_amount: U128,
+    pub rate_bump: u32,
+    pub resolver_fee: U128,
+    pub protocol_fee: U128,
+    pub integrator_fee: U128,
+    /// Part of the auction bump paid above the order's base rate.
+    pub surplus: U128,
+    pub maker_surplus: U128,
+    pub protocol_surplus: U128,
+    pub maker_receives: U128,
+    pub taker_receives: U128,
+    pub filled_at: u64,
+}
+
+/// Making amounts to take from each side of `match_orders`.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchAmounts {
+    pub making_amount_a: U128,
+    pub making_amount_b: U128,
+}
+
+/// Outcome of `match_orders`. Spreads are credited to the matcher's balance,
+/// `spread_a` in order A's maker asset and `spread_b` in order B's.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MatchResult {
+    pub fill_a: FillReceipt,
+    pub fill_b: FillReceipt,
+    pub spread_a: U128,
+    pub spread_b: U128,
+}
+
+/// A settled fill as kept in an order's fill history.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FillRecord {
+    pub taker: AccountId,
+    pub making_amount: U128,
+    pub taking_amount: U128,
+    pub actual_taking_amount: U128,
+    pub rate_bump: u32,
+    pub resolver_fee: U128,
+    pub protocol_fee: U128,
+    pub integrator_fee: U128,
+    pub protocol_surplus: U128,
+    pub filled_at: u64,
+}
+
+/// Settled volume for a `(maker_asset, taker_asset)` pair.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PairStats {
+    pub making_volume: U128,
+    pub taking_volume: U128,
+    pub fill_count: u64,
+}
+
+impl Default for PairStats {
+    fn default() -> Self {
+        Self {
+            making_volume: U128(0),
+            taking_volume: U128(0),
+            fill_count: 0,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingProtocolFee {
+    pub receiver: AccountId,
+    pub basis_points: u32,
+    pub effective_at: u64,
+}
+
+/// How the auction surplus of a fill is split. Whatever neither share claims
+/// is not charged to the taker.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SurplusShares {
+    pub maker_basis_points: u32,
+    pub protocol_basis_points: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProtocolFeeConfig {
+    pub receiver: AccountId,
+    pub basis_points: u32,
+    pub pending: Option<PendingProtocolFee>,
+}
+
+/// Read-only snapshot of an order as returned by the order book views.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderView {
+    pub order_hash: [u8; 32],
+    pub order: FusionOrder,
+    pub amended_terms: Option<OrderTerms>,
+    pub auction: Option<AuctionDetails>,
+    pub created_at: u64,
+    pub is_cancelled: bool,
+    /// Past its expiry: listed until pruned, but no longer fillable.
+    pub is_expired: bool,
+    pub remaining_making_amount: U128,
+    pub remaining_taking_amount: U128,
+    /// Auction bump (in basis points) that a fill would pay right now.
+    pub rate_bump: u32,
+    /// Taking amount for the whole remainder at the current auction price.
+    pub current_taking_amount: U128,
 }
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct FusionOrderContract {
     orders: UnorderedMap<[u8; 32], OrderState>,
+    orders_by_maker: LookupMap<AccountId, UnorderedSet<[u8; 32]>>,
+    active_orders_by_pair: LookupMap<(AccountId, AccountId), UnorderedSet<[u8; 32]>>,
+    open_orders: TreeMap<(u64, [u8; 32]), ()>,
     resolver_whitelist: UnorderedMap<AccountId, bool>,
     protocol_fee_receiver: AccountId,
     protocol_fee_basis_points: u32,
+    pending_protocol_fee: Option<PendingProtocolFee>,
+    surplus_shares: SurplusShares,
+    protocol_fees: UnorderedMap<AccountId, u128>,
+    integrator_fees: LookupMap<(AccountId, AccountId), u128>,
+    balances: LookupMap<(AccountId, AccountId), u128>,
+    maker_nonces: LookupMap<AccountId, u64>,
+    fill_history: LookupMap<[u8; 32], Vec<FillRecord>>,
+    pair_stats: LookupMap<(AccountId, AccountId), PairStats>,
+    bidding_rounds: LookupMap<[u8; 32], BiddingRound>,
+    predicate_oracles: UnorderedSet<AccountId>,
+    /// NEAR prepaid through `storage_deposit` for balance entries created by deposits.
+    storage_balances: LookupMap<AccountId, u128>,
 }
 
 #[near_bindgen]
 impl FusionOrderContract {
     #[init]
     pub fn new(protocol_fee_receiver: AccountId, protocol_fee_basis_points: u32) -> Self {
-        assert!(protocol_fee_basis_points <= BASIS_POINTS, "Invalid fee");
+        assert!(protocol_fee_basis_points <= MAX_PROTOCOL_FEE_BASIS_POINTS, "Invalid fee");
         Self {
             orders: UnorderedMap::new(b"o"),
+            orders_by_maker: LookupMap::new(b"m"),
+            active_orders_by_pair: LookupMap::new(b"p"),
+            open_orders: TreeMap::new(b"t"),
             resolver_whitelist: UnorderedMap::new(b"r"),
             protocol_fee_receiver,
             protocol_fee_basis_points,
+            pending_protocol_fee: None,
+            surplus_shares: SurplusShares {
+                maker_basis_points: BASIS_POINTS,
+                protocol_basis_points: 0,
+            },
+            protocol_fees: UnorderedMap::new(b"f"),
+            integrator_fees: LookupMap::new(b"i"),
+            balances: LookupMap::new(b"b"),
+            maker_nonces: LookupMap::new(b"n"),
+            fill_history: LookupMap::new(b"h"),
+            pair_stats: LookupMap::new(b"s"),
+            bidding_rounds: LookupMap::new(b"g"),
+            predicate_oracles: UnorderedSet::new(b"q"),
+            storage_balances: LookupMap::new(b"e"),
         }
     }
 
+    /// Posts an order. The maker attaches a deposit covering the order's storage;
+    /// anything above the actual cost is refunded.
+    #[payable]
     pub fn create_order(&mut self, order: FusionOrder, auction: Option<AuctionDetails>) -> [u8; 32] {
-        let maker = env::predecessor_account_id();
-        assert_eq!(order.maker, maker, "Only maker can create order");
-        
-        let order_hash = order.hash();
-        assert!(!self.orders.get(&order_hash).is_some(), "Order exists");
-        
-        let order_state = OrderState {
-            order,
-            filled_making_amount: 0,
-            filled_taking_amount: 0,
-            is_cancelled: false,
-            auction,
-        };
-        
-        self.orders.insert(&order_hash, &order_state);
-        order_hash
+        self._create_order(order, auction, None, None)
+    }
+
+    /// Posts an order whose making amount unlocks slice by slice on `twap`'s
+    /// schedule. Storage is paid as for `create_order`.
+    #[payable]
+    pub fn create_twap_order(
+        &mut self,
+        order: FusionOrder,
+        auction: Option<AuctionDetails>,
+        twap: TwapSchedule,
+    ) -> [u8; 32] {
+        assert!(twap.is_valid(auction.as_ref()), "Invalid TWAP schedule");
+        assert!(order.making_amount.0 >= twap.slice_count as u128, "TWAP slices too small");
+        self._create_order(order, auction, Some(twap), None)
+    }
+
+    /// Posts a dollar-cost-averaging order: the making amount is the total budget
+    /// and at most `recurring.amount_per_period` of it can be filled per period.
+    #[payable]
+    pub fn create_recurring_order(
+        &mut self,
+        order: FusionOrder,
+        auction: Option<AuctionDetails>,
+        recurring: RecurringSchedule,
+    ) -> [u8; 32] {
+        assert!(
+            recurring.period > 0
+                && recurring.amount_per_period.0 > 0
+                && recurring.amount_per_period.0 <= order.making_amount.0,
+            "Invalid recurring schedule"
+        );
+        self._create_order(order, auction, None, Some(recurring))
     }
 
+    /// Fills an order from the caller's deposited `taker_asset` balance. Resolves
+    /// to the amount refunded to that balance, which is non-zero only if the
+    /// payout to the taker failed and the fill was rolled back. `limits` makes
+    /// the fill revert if the auction price, fees or timing move past them.
     pub fn fill_order(
         &mut self,
         order_hash: [u8; 32],
         making_amount: U128,
         taking_amount: U128,
         resolver_fee: Option<ResolverFee>,
-    ) {
+        limits: Option<FillLimits>,
+    ) -> Promise {
         let taker = env::predecessor_account_id();
-        let mut order_state = self.orders.get(&order_hash).expect("Order not found");
-        
-        assert!(!order_state.is_cancelled, "Order cancelled");
-        
-        let making_amount = making_amount.0;
-        let taking_amount = taking_amount.0;
-        
-        assert!(
-            order_state.order.is_valid_partial_fill(making_amount, taking_amount),
-            "Invalid fill amounts"
-        );
+        let request = FillRequest {
+            fill: FillAmount::Exact(making_amount, taking_amount),
+            resolver_fee,
+            limits: limits.unwrap_or_default(),
+        };
+        self._start_fill(taker, order_hash, request, None)
+    }
+
+    /// Fills `making_amount` of the order, capped at what remains. The taking
+    /// amount is derived from the order price and rounded up, then the current
+    /// auction bump is added.
+    pub fn fill_order_by_making(
+        &mut self,
+        order_hash: [u8; 32],
+        making_amount: U128,
+        resolver_fee: Option<ResolverFee>,
+        limits: Option<FillLimits>,
+    ) -> Promise {
+        let taker = env::predecessor_account_id();
+        let request = FillRequest {
+            fill: FillAmount::Making(making_amount),
+            resolver_fee,
+            limits: limits.unwrap_or_default(),
+        };
+        self._start_fill(taker, order_hash, request, None)
+    }
+
+    /// Fills the order for `taking_amount` at its base price, capped at what is
+    /// fillable now. The making amount is rounded down and the current auction bump is
+    /// charged on top.
+    pub fn fill_order_by_taking(
+        &mut self,
+        order_hash: [u8; 32],
+        taking_amount: U128,
+        resolver_fee: Option<ResolverFee>,
+        limits: Option<FillLimits>,
+    ) -> Promise {
+        let taker = env::predecessor_account_id();
+        let request = FillRequest {
+            fill: FillAmount::Taking(taking_amount),
+            resolver_fee,
+            limits: limits.unwrap_or_default(),
+        };
+        self._start_fill(taker, order_hash, request, None)
+    }
+
+    /// NEP-141 receiver. Makers top up their balance with a `deposit` message and
+    /// takers fill an order in one step with a `fill` message. Whatever the fill
+    /// does not consume is returned to the sender by the token contract.
+    pub fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token = env::predecessor_account_id();
+        let message = if msg.is_empty() {
+            TransferMessage::Deposit
+        } else {
+            serde_json::from_str(&msg).expect("Invalid transfer message")
+        };
         
-        let remaining_making = order_state.order.making_amount.0 - order_state.filled_making_amount;
-        let remaining_taking = order_state.order.taking_amount.0 - order_state.filled_taking_amount;
+        let (order_hash, request) = match message {
+            TransferMessage::Deposit => {
+                self._deposit_with_storage(&sender_id, &sender_id, &token, amount.0);
+                return PromiseOrValue::Value(U128(0));
+            }
+            TransferMessage::Fill {
+                order_hash,
+                making_amount,
+                taking_amount,
+                resolver_fee,
+                limits,
+            } => (order_hash, FillRequest {
+                fill: FillAmount::Exact(making_amount, taking_amount),
+                resolver_fee,
+                limits,
+            }),
+            TransferMessage::FillByMaking {
+                order_hash,
+                making_amount,
+                resolver_fee,
+                limits,
+            } => (order_hash, FillRequest {
+                fill: FillAmount::Making(making_amount),
+                resolver_fee,
+                limits,
+            }),
+            TransferMessage::FillByTaking {
+                order_hash,
+                taking_amount,
+                resolver_fee,
+                limits,
+            } => (order_hash, FillRequest {
+                fill: FillAmount::Taking(taking_amount),
+                resolver_fee,
+                limits,
+            }),
+        };
         
-        assert!(making_amount <= remaining_making, "Exceeds available");
-        assert!(taking_amount <= remaining_taking, "Exceeds required");
+        let order_state = self.orders.get(&order_hash).expect("Order not found");
+        assert_eq!(order_state.order.taker_asset, token, "Wrong token for order");
         
-        let mut actual_taking_amount = taking_amount;
+        PromiseOrValue::Promise(self._start_fill(sender_id, order_hash, request, Some(amount.0)))
+    }
+
+    /// Completes a fill once the order's remote predicate reads have returned.
+    #[private]
+    pub fn on_predicate_checked(
+        &mut self,
+        taker: AccountId,
+        order_hash: [u8; 32],
+        request: FillRequest,
+        transfer_amount: Option<U128>,
+    ) -> Promise {
+        let results: Vec<Option<Vec<u8>>> = (0..env::promise_results_count())
+            .map(|i| match env::promise_result(i) {
+                PromiseResult::Successful(result) => Some(result),
+                _ => None,
+            })
+            .collect();
+        self._execute_fill(&taker, order_hash, request, transfer_amount.map(|a| a.0), &results)
+    }
+
+    /// Prepays storage for `account_id` (the caller by default). A deposit that
+    /// opens a new token balance takes its storage from here.
+    #[payable]
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>) -> U128 {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let balance = self.storage_balances.get(&account_id).unwrap_or(0) + env::attached_deposit();
+        self.storage_balances.insert(&account_id, &balance);
+        U128(balance)
+    }
+
+    /// Returns the caller's unused prepaid storage. Storage already taken by
+    /// balance entries is not given back.
+    pub fn storage_withdraw(&mut self) -> U128 {
+        let account_id = env::predecessor_account_id();
+        let balance = self.storage_balances.remove(&account_id).unwrap_or(0);
+        assert!(balance > 0, "Nothing to withdraw");
+        Promise::new(account_id).transfer(balance);
+        U128(balance)
+    }
+
+    pub fn get_storage_balance(&self, account_id: AccountId) -> U128 {
+        U128(self.storage_balances.get(&account_id).unwrap_or(0))
+    }
+
+    /// Invalidates every order the caller signed with a `nonce_equals` predicate
+    /// for the current nonce.
+    pub fn increment_nonce(&mut self) -> u64 {
+        let maker = env::predecessor_account_id();
+        let nonce = self.maker_nonces.get(&maker).unwrap_or(0) + 1;
+        self.maker_nonces.insert(&maker, &nonce);
+        nonce
+    }
+
+    pub fn get_nonce(&self, maker: AccountId) -> u64 {
+        self.maker_nonces.get(&maker).unwrap_or(0)
+    }
+
+    /// Runs once the maker asset has been sent to the taker. On success the
+    /// taker asset is forwarded to the maker; on failure the fill is reversed and
+    /// the taker's payment refunded. Returns the amount handed back to the taker;
+    /// for a transfer call fill this is what the token contract refunds.
+    #[private]
+    pub fn on_fill_settled(&mut self, receipt: FillReceipt, unused: U128, transfer_amount: Option<U128>) -> U128 {
+        let is_settled = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        let order_state = self._complete_fill(&receipt, is_settled);
         
-        if let Some(auction) = &order_state.auction {
-            let rate_bump = auction.get_rate_bump(env::block_timestamp());
-            actual_taking_amount = taking_amount + (taking_amount * rate_bump as u128) / BASIS_POINTS as u128;
+        if is_settled {
+            self._ft_transfer(
+                &order_state.order.taker_asset,
+                &order_state.order.maker,
+                receipt.maker_receives.0,
+                "Fusion fill",
+            ).then(
+                Promise::new(env::current_account_id()).function_call(
+                    "on_withdraw".to_string(),
+                    serde_json::json!({
+                        "account_id": order_state.order.maker,
+                        "token": order_state.order.taker_asset,
+                        "amount": receipt.maker_receives,
+                    }).to_string().as_bytes().to_vec(),
+                    0,
+                    GAS_FOR_CALLBACK,
+                )
+            );
+            return unused;
         }
         
-        if let Some(resolver_fee) = resolver_fee {
-            if let Some(resolver) = &order_state.order.resolver {
-                assert_eq!(resolver_fee.receiver, *resolver, "Invalid resolver");
-                assert!(
-                    self.resolver_whitelist.get(resolver).unwrap_or(false),
-                    "Resolver not whitelisted"
-                );
-                actual_taking_amount += resolver_fee.amount.0;
-            }
+        self._revert_fill(&receipt);
+        
+        // A transfer call refund is paid back by the token contract itself, but it
+        // can return at most the transferred amount. Whatever the fill drew from
+        // the taker's existing deposit goes back to that deposit.
+        let refund = unused.0 + receipt.actual_taking_amount.0;
+        let token_refund = transfer_amount.map_or(0, |amount| refund.min(amount.0));
+        if refund > token_refund {
+            self._deposit(&receipt.taker, &order_state.order.taker_asset, refund - token_refund);
         }
         
-        let protocol_fee = (making_amount * self.protocol_fee_basis_points as u128) / BASIS_POINTS as u128;
-        let maker_receives = actual_taking_amount;
-        let taker_receives = making_amount - protocol_fee;
+        emit_event("fill_failed", serde_json::json!({
+            "order_hash": receipt.order_hash,
+            "taker": receipt.taker,
+            "making_amount": receipt.making_amount,
+            "refunded_amount": receipt.actual_taking_amount,
+            "reason": "Transfer of maker asset to taker failed",
+        }));
         
-        Promise::new(order_state.order.taker_asset.clone()).function_call(
-            "ft_transfer_from".as_bytes().to_vec(),
-            format!(
-                r#"{{"sender_id":"{}","receiver_id":"{}","amount":"{}","memo":"Fusion fill"}}"#,
-                taker,
-                order_state.order.maker,
-                maker_receives
-            )
-            .as_bytes()
-            .to_vec(),
-            1,
-            GAS_FOR_FT_TRANSFER,
+        U128(if transfer_amount.is_some() { token_refund } else { refund })
+    }
+
+    /// Settles two crossing orders (A sells X for Y, B sells Y for X) against
+    /// each other, with the caller as the taker of both. Each order is filled
+    /// with the usual checks and fees, and everything settles on internal
+    /// balances. What one order gives beyond what the other asks is the
+    /// caller's spread. Balances the match opens are paid from the caller's
+    /// prepaid storage.
+    pub fn match_orders(
+        &mut self,
+        order_hash_a: [u8; 32],
+        order_hash_b: [u8; 32],
+        amounts: MatchAmounts,
+    ) -> MatchResult {
+        let matcher = env::predecessor_account_id();
+        let order_a = self.orders.get(&order_hash_a).expect("Order not found").effective_order();
+        let order_b = self.orders.get(&order_hash_b).expect("Order not found").effective_order();
+        assert!(
+            order_a.maker_asset == order_b.taker_asset && order_a.taker_asset == order_b.maker_asset,
+            "Orders do not cross"
+        );
+        // B must offer at least A's price before auction bumps and fees are counted
+        assert!(
+            order_a.is_price_respected(order_b.taking_amount.0, order_b.making_amount.0),
+            "Prices do not cross"
+        );
+        // Matching fills both orders in this call, with no results for remote reads
+        assert!(
+            [&order_a, &order_b].iter().all(|order| {
+                order.predicate.as_ref().map_or(true, |predicate| predicate::remote_calls(predicate).is_empty())
+            }),
+            "Cannot match orders with remote predicates"
         );
         
-        Promise::new(order_state.order.maker_asset.clone()).function_call(
-            "ft_transfer_from".as_bytes().to_vec(),
-            format!(
-                r#"{{"sender_id":"{}","receiver_id":"{}","amount":"{}","memo":"Fusion fill"}}"#,
-                order_state.order.maker,
-                taker,
-                taker_receives
-            )
-            .as_bytes()
-            .to_vec(),
-            1,
-            GAS_FOR_FT_TRANSFER,
+        // B's proceeds pay for A, so lend the matcher B's side until B is filled
+        let advance = amounts.making_amount_b.0;
+        self._deposit_with_storage(&matcher, &matcher, &order_a.taker_asset, advance);
+        
+        let fill_a = self._fill_order(&matcher, order_hash_a, FillRequest {
+            fill: FillAmount::Making(amounts.making_amount_a),
+            resolver_fee: None,
+            limits: FillLimits::default(),
+        }, &[]);
+        self._deposit_with_storage(&matcher, &matcher, &order_a.maker_asset, fill_a.taker_receives.0);
+        
+        let fill_b = self._fill_order(&matcher, order_hash_b, FillRequest {
+            fill: FillAmount::Making(amounts.making_amount_b),
+            resolver_fee: None,
+            limits: FillLimits::default(),
+        }, &[]);
+        assert!(
+            fill_a.taker_receives.0 >= fill_b.actual_taking_amount.0
+                && fill_b.taker_receives.0 >= fill_a.actual_taking_amount.0,
+            "Prices do not cross"
         );
+        self._deposit_with_storage(&matcher, &matcher, &order_b.maker_asset, fill_b.taker_receives.0);
+        self._withdraw(&matcher, &order_a.taker_asset, advance);
         
-        if protocol_fee > 0 {
-            Promise::new(order_state.order.maker_asset.clone()).function_call(
-                "ft_transfer_from".as_bytes().to_vec(),
-                format!(
-                    r#"{{"sender_id":"{}","receiver_id":"{}","amount":"{}","memo":"Protocol fee"}}"#,
-                    order_state.order.maker,
-                    self.protocol_fee_receiver,
-                    protocol_fee
-                )
-                .as_bytes()
-                .to_vec(),
-                1,
-                GAS_FOR_FT_TRANSFER,
-            );
-        }
+        self._deposit_with_storage(&matcher, &order_a.maker, &order_a.taker_asset, fill_a.maker_receives.0);
+        self._deposit_with_storage(&matcher, &order_b.maker, &order_b.taker_asset, fill_b.maker_receives.0);
+        self._complete_fill(&fill_a, true);
+        self._complete_fill(&fill_b, true);
         
-        order_state.filled_making_amount += making_amount;
-        order_state.filled_taking_amount += taking_amount;
+        let result = MatchResult {
+            spread_a: U128(fill_a.taker_receives.0 - fill_b.actual_taking_amount.0),
+            spread_b: U128(fill_b.taker_receives.0 - fill_a.actual_taking_amount.0),
+            fill_a,
+            fill_b,
+        };
+        emit_event("orders_matched", serde_json::json!({
+            "order_hash_a": order_hash_a,
+            "order_hash_b": order_hash_b,
+            "matcher": matcher,
+            "making_amount_a": result.fill_a.making_amount,
+            "making_amount_b": result.fill_b.making_amount,
+            "spread_a": result.spread_a,
+            "spread_b": result.spread_b,
+        }));
+        result
+    }
+
+    pub fn withdraw(&mut self, token: AccountId, amount: Option<U128>) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let amount = amount.map(|a| a.0).unwrap_or_else(|| self._balance_of(&account_id, &token));
+        assert!(amount > 0, "Nothing to withdraw");
+        self._withdraw(&account_id, &token, amount);
         
-        self.orders.insert(&order_hash, &order_state);
+        self._ft_transfer(&token, &account_id, amount, "Fusion withdrawal").then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_withdraw".to_string(),
+                serde_json::json!({
+                    "account_id": account_id,
+                    "token": token,
+                    "amount": U128(amount),
+                }).to_string().as_bytes().to_vec(),
+                0,
+                GAS_FOR_CALLBACK,
+            )
+        )
     }
 
-    pub fn cancel_order(&mut self, order_hash: [u8; 32]) {
+    #[private]
+    pub fn on_withdraw(&mut self, account_id: AccountId, token: AccountId, amount: U128) {
+        if !matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            self._deposit(&account_id, &token, amount.0);
+        }
+    }
+
+    /// Cancels the unfilled remainder of an order. The making amount still
+    /// reserved for it goes back to the maker's balance, where it can be withdrawn,
+    /// and the final summary is stored on the order and returned. Reopening that
+    /// balance is paid from the maker's prepaid storage.
+    pub fn cancel_order(&mut self, order_hash: [u8; 32]) -> OrderSettlement {
         let maker = env::predecessor_account_id();
         let mut order_state = self.orders.get(&order_hash).expect("Order not found");
         
         assert_eq!(order_state.order.maker, maker, "Only maker can cancel");
         assert!(!order_state.is_cancelled, "Already cancelled");
+        assert!(order_state.settlement.is_none(), "Order already settled");
+        
+        let released_making_amount = order_state.remaining_making_amount();
+        let settlement = OrderSettlement {
+            reason: SettlementReason::Cancelled,
+            filled_making_amount: U128(order_state.filled_making_amount),
+            filled_taking_amount: U128(order_state.filled_taking_amount),
+            released_making_amount: U128(released_making_amount),
+            settled_at: env::block_timestamp(),
+        };
         
         order_state.is_cancelled = true;
+        order_state.settlement = Some(settlement.clone());
+        self._deposit_with_storage(&maker, &maker, &order_state.order.maker_asset, released_making_amount);
+        self._deactivate_order(&order_hash, &order_state);
         self.orders.insert(&order_hash, &order_state);
+        
+        emit_event("order_cancelled", serde_json::json!({
+            "order_hash": order_hash,
+            "maker": maker,
+            "settlement": settlement,
+        }));
+        settlement
     }
 
-    pub fn add_resolver(&mut self, resolver: AccountId) {
-        assert_eq!(
-            env::predecessor_account_id(),
-            env::current_account_id(),
-            "Only contract can add resolvers"
+    /// Removes a fully filled, cancelled or expired order together with its fill
+    /// history and bidding round. An expired order's unfilled reservation returns
+    /// to the maker's balance. The storage deposit is refunded to the maker, less
+    /// a tip for the caller when that is someone else.
+    pub fn prune_order(&mut self, order_hash: [u8; 32]) -> U128 {
+        let caller = env::predecessor_account_id();
+        let order_state = self.orders.get(&order_hash).expect("Order not found");
+        let maker = order_state.order.maker.clone();
+        
+        let is_expired = order_state.is_expired(env::block_timestamp());
+        assert!(!order_state.is_active() || is_expired, "Order still active");
+        assert_eq!(order_state.fills_in_flight, 0, "Fill in flight");
+        assert!(
+            self.bidding_rounds.get(&order_hash).map_or(true, |round| round.is_settled),
+            "Bidding not settled"
         );
-        self.resolver_whitelist.insert(&resolver, &true);
+        
+        if order_state.is_active() {
+            self._deposit(&maker, &order_state.order.maker_asset, order_state.remaining_making_amount());
+            self._deactivate_order(&order_hash, &order_state);
+        }
+        
+        self.orders.remove(&order_hash);
+        self.fill_history.remove(&order_hash);
+        self.bidding_rounds.remove(&order_hash);
+        if let Some(mut maker_orders) = self.orders_by_maker.get(&maker) {
+            maker_orders.remove(&order_hash);
+            self.orders_by_maker.insert(&maker, &maker_orders);
+        }
+        
+        let tip = if caller == maker {
+            0
+        } else {
+            order_state.storage_deposit * PRUNE_TIP_BASIS_POINTS as u128 / BASIS_POINTS as u128
+        };
+        if tip > 0 {
+            Promise::new(caller.clone()).transfer(tip);
+        }
+        if order_state.storage_deposit > tip {
+            Promise::new(maker.clone()).transfer(order_state.storage_deposit - tip);
+        }
+        
+        emit_event("order_pruned", serde_json::json!({
+            "order_hash": order_hash,
+            "maker": maker,
+            "pruned_by": caller,
+            "storage_refund": U128(order_state.storage_deposit - tip),
+            "tip": U128(tip),
+        }));
+        U128(tip)
     }
 
-    pub fn remove_resolver(&mut self, resolver: AccountId) {
-        assert_eq!(
-            env::predecessor_account_id(),
-            env::current_account_id(),
+    /// Tightens a live order in place, keeping its hash and its place in the order
+    /// book. The maker can shrink the remaining making amount (the released part
+    /// returns to their balance), ask a higher price for the remainder, or extend
+    /// the auction or expiry. The signed order is left as is and the new terms are
+    /// stored beside it. Nothing can be amended while a fill is settling, and
+    /// each amendment's storage is paid from the attached deposit. A balance
+    /// reopened for the released part is paid from the maker's prepaid storage,
+    /// as it outlives the order.
+    #[payable]
+    pub fn amend_order(
+        &mut self,
+        order_hash: [u8; 32],
+        making_amount: Option<U128>,
+        taking_amount: Option<U128>,
+        auction_duration: Option<u64>,
+        expiry: Option<u64>,
+    ) -> OrderAmendment {
+        let maker = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let mut order_state = self.orders.get(&order_hash).expect("Order not found");
+        
+        assert_eq!(order_state.order.maker, maker, "Only maker can amend");
+        assert!(order_state.is_active(), "Order not active");
+        assert_eq!(order_state.fills_in_flight, 0, "Fill in flight");
+        assert!(
+            self.bidding_rounds.get(&order_hash).map_or(true, |round| round.is_settled),
+            "Bidding in progress"
+        );
+        assert!(
+            making_amount.is_some() || taking_amount.is_some() || auction_duration.is_some() || expiry.is_some(),
+            "Nothing to amend"
+        );
+        assert!(order_state.amendments.len() < MAX_AMENDMENTS, "Too many amendments");
+        
+        let previous_making_amount = order_state.remaining_making_amount();
+        let previous_taking_amount = order_state.remaining_taking_amount();
+        let mut terms = order_state.terms();
+        let mut released_making_amount = 0;
+        
+        if making_amount.is_some() || taking_amount.is_some() {
+            assert!(order_state.twap.is_none(), "Cannot resize TWAP order");
+            let new_making_amount = making_amount.map_or(previous_making_amount, |a| a.0);
+            assert!(
+                new_making_amount > 0 && new_making_amount <= previous_making_amount,
+                "Can only reduce making amount"
+            );
+            let new_taking_amount = taking_amount.map_or_else(
+                || mul_div(new_making_amount, terms.taking_amount.0, terms.making_amount.0, true),
+                |a| a.0,
+            );
+            assert!(
+                order_state.effective_order().is_price_respected(new_making_amount, new_taking_amount),
+                "Can only tighten price"
+            );
+            assert!(
+                new_making_amount < previous_making_amount || new_taking_amount > previous_taking_amount,
+                "Nothing to amend"
+            );
+            
+            released_making_amount = previous_making_amount - new_making_amount;
+            
+            // Fills so far stay counted; later ones are priced on the new terms alone
+            terms.making_amount = U128(new_making_amount);
+            terms.taking_amount = U128(new_taking_amount);
+            terms.filled_making_base = U128(order_state.filled_making_amount);
+            terms.filled_taking_base = U128(order_state.filled_taking_amount);
+        }
+        
+        if let Some(duration) = auction_duration {
+            let auction = order_state.auction.as_mut().expect("Order has no auction");
+            assert!(duration > auction.duration, "Can only extend auction");
+            auction.duration = duration;
+        }
+        
+        if let Some(expiry) = expiry {
+            let current = terms.expiry.expect("Order has no expiry");
+            assert!(expiry > current, "Can only extend expiry");
+            terms.expiry = Some(expiry);
+        }
+        order_state.amended_terms = Some(terms);
+        
+        let amendment = OrderAmendment {
+            previous_making_amount: U128(previous_making_amount),
+            previous_taking_amount: U128(previous_taking_amount),
+            making_amount: U128(order_state.remaining_making_amount()),
+            taking_amount: U128(order_state.remaining_taking_amount()),
+            auction_duration,
+            expiry,
+            amended_at: env::block_timestamp(),
+        };
+        order_state.amendments.push(amendment.clone());
+        self.orders.insert(&order_hash, &order_state);
+        
+        order_state.storage_deposit += self._charge_storage(initial_storage_usage, 0, &maker);
+        self.orders.insert(&order_hash, &order_state);
+        
+        self._deposit_with_storage(&maker, &maker, &order_state.order.maker_asset, released_making_amount);
+        
+        emit_event("order_amended", serde_json::json!({
+            "order_hash": order_hash,
+            "maker": maker,
+            "amendment": amendment,
+        }));
+        amendment
+    }
+
+    /// Starts a sealed-bid round for an order. Fills wait until bids are
+    /// revealed; the best bid then gets an exclusive window to fill the order at
+    /// its committed auction bump. The maker pays for the round's storage,
+    /// including room for `MAX_BIDS` bids, on top of the order's deposit.
+    #[payable]
+    pub fn open_bidding(&mut self, order_hash: [u8; 32], schedule: BiddingSchedule) {
+        let initial_storage_usage = env::storage_usage();
+        let mut order_state = self.orders.get(&order_hash).expect("Order not found");
+        assert_eq!(order_state.order.maker, env::predecessor_account_id(), "Only maker can open bidding");
+        assert!(order_state.is_active(), "Order not active");
+        assert!(
+            order_state.twap.is_none() && order_state.recurring.is_none(),
+            "Bidding needs a plain order"
+        );
+        assert!(
+            self.bidding_rounds.get(&order_hash).map_or(true, |round| round.is_settled),
+            "Bidding already open"
+        );
+        let now = env::block_timestamp();
+        assert!(
+            now < schedule.commit_end && schedule.commit_end < schedule.reveal_end && schedule.fill_window > 0,
+            "Invalid bidding schedule"
+        );
+        
+        self.bidding_rounds.insert(&order_hash, &BiddingRound {
+            schedule,
+            making_amount: U128(order_state.remaining_making_amount()),
+            bids: Vec::new(),
+            winner: None,
+            winner_filled: U128(0),
+            is_settled: false,
+        });
+        
+        let reserved = bidding::MAX_BIDS as u64 * SEALED_BID_STORAGE;
+        order_state.storage_deposit +=
+            self._charge_storage(initial_storage_usage, reserved, &order_state.order.maker);
+        self.orders.insert(&order_hash, &order_state);
+    }
+
+    /// Commits a whitelisted resolver to a hidden bid. The attached deposit must
+    /// equal the round's bond.
+    #[payable]
+    pub fn commit_bid(&mut self, order_hash: [u8; 32], commitment: [u8; 32]) {
+        let resolver = env::predecessor_account_id();
+        assert!(
+            self.resolver_whitelist.get(&resolver).unwrap_or(false),
+            "Resolver not whitelisted"
+        );
+        let mut round = self.bidding_rounds.get(&order_hash).expect("No bidding round");
+        assert!(!round.is_settled && env::block_timestamp() < round.schedule.commit_end, "Commit phase over");
+        assert_eq!(env::attached_deposit(), round.schedule.bond.0, "Wrong bond");
+        assert!(round.bids.iter().all(|bid| bid.resolver != resolver), "Already committed");
+        assert!(round.bids.len() < bidding::MAX_BIDS, "Too many bids");
+        
+        round.bids.push(SealedBid {
+            resolver: resolver.clone(),
+            commitment,
+            rate_bump: None,
+        });
+        self.bidding_rounds.insert(&order_hash, &round);
+        
+        emit_event("bid_committed", serde_json::json!({
+            "order_hash": order_hash,
+            "resolver": resolver,
+        }));
+    }
+
+    pub fn reveal_bid(&mut self, order_hash: [u8; 32], rate_bump: u32, salt: String) {
+        let resolver = env::predecessor_account_id();
+        let mut round = self.bidding_rounds.get(&order_hash).expect("No bidding round");
+        let now = env::block_timestamp();
+        assert!(!round.is_settled, "Bidding settled");
+        assert!(
+            now >= round.schedule.commit_end && now < round.schedule.reveal_end,
+            "Not in reveal phase"
+        );
+        
+        let index = round
+            .bids
+            .iter()
+            .position(|bid| bid.resolver == resolver)
+            .expect("No bid committed");
+        assert!(round.bids[index].rate_bump.is_none(), "Already revealed");
+        assert_eq!(
+            round.bids[index].commitment,
+            bidding::commitment(&resolver, rate_bump, &salt),
+            "Commitment mismatch"
+        );
+        // A bump over 100% would leave the winner unable to fill while it holds the window
+        assert!(rate_bump <= BASIS_POINTS, "Rate bump too high");
+        // The winner's bump replaces the auction from `reveal_end`, so it may not undercut it
+        let order_state = self.orders.get(&order_hash).expect("Order not found");
+        assert!(
+            rate_bump >= order_state.rate_bump(round.schedule.reveal_end),
+            "Rate bump below auction"
+        );
+        
+        round.bids[index].rate_bump = Some(rate_bump);
+        let is_best = round
+            .winner
+            .map_or(true, |winner| rate_bump > round.bids[winner].rate_bump.unwrap_or(0));
+        if is_best {
+            round.winner = Some(index);
+        }
+        self.bidding_rounds.insert(&order_hash, &round);
+        
+        emit_event("bid_revealed", serde_json::json!({
+            "order_hash": order_hash,
+            "resolver": resolver,
+            "rate_bump": rate_bump,
+        }));
+    }
+
+    /// Returns bonds once the round is over. Revealed losing bids get theirs back
+    /// and so does a winner who filled in full. Unrevealed bids and a winner who
+    /// did not fill forfeit their bond to the maker, unless the maker cancelled.
+    /// Returns the forfeited amount.
+    pub fn settle_bidding(&mut self, order_hash: [u8; 32]) -> U128 {
+        let mut round = self.bidding_rounds.get(&order_hash).expect("No bidding round");
+        let order_state = self.orders.get(&order_hash).expect("Order not found");
+        assert!(!round.is_settled, "Already settled");
+        assert!(
+            env::block_timestamp() >= round.window_end()
+                || order_state.is_cancelled
+                || round.winner_filled_in_full(),
+            "Bidding not over"
+        );
+        
+        let bond = round.schedule.bond.0;
+        let mut forfeited = 0;
+        for (index, bid) in round.bids.iter().enumerate() {
+            let keeps_bond = if round.winner == Some(index) {
+                round.winner_filled_in_full()
+            } else {
+                bid.rate_bump.is_some()
+            };
+            if keeps_bond || order_state.is_cancelled {
+                Promise::new(bid.resolver.clone()).transfer(bond);
+            } else {
+                forfeited += bond;
+            }
+        }
+        if forfeited > 0 {
+            Promise::new(order_state.order.maker.clone()).transfer(forfeited);
+        }
+        
+        round.is_settled = true;
+        self.bidding_rounds.insert(&order_hash, &round);
+        
+        emit_event("bidding_settled", serde_json::json!({
+            "order_hash": order_hash,
+            "winner": round.winner.map(|index| round.bids[index].resolver.clone()),
+            "forfeited": U128(forfeited),
+        }));
+        U128(forfeited)
+    }
+
+    pub fn get_bidding_round(&self, order_hash: [u8; 32]) -> Option<BiddingRound> {
+        self.bidding_rounds.get(&order_hash)
+    }
+
+    /// Commitment to pass to `commit_bid`; best computed off-chain to keep the salt private.
+    pub fn get_bid_commitment(&self, resolver: AccountId, rate_bump: u32, salt: String) -> [u8; 32] {
+        bidding::commitment(&resolver, rate_bump, &salt)
+    }
+
+    pub fn add_resolver(&mut self, resolver: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can add resolvers"
+        );
+        self.resolver_whitelist.insert(&resolver, &true);
+    }
+
+    pub fn remove_resolver(&mut self, resolver: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
             "Only contract can remove resolvers"
         );
         self.resolver_whitelist.remove(&resolver);
     }
 
+    /// Allows order predicates to read from `oracle`.
+    pub fn add_predicate_oracle(&mut self, oracle: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can add oracles"
+        );
+        assert_ne!(oracle, env::current_account_id(), "Invalid oracle");
+        self.predicate_oracles.insert(&oracle);
+    }
+
+    /// Stops predicates from reading `oracle`. Open orders that depend on it
+    /// can no longer be filled.
+    pub fn remove_predicate_oracle(&mut self, oracle: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can remove oracles"
+        );
+        self.predicate_oracles.remove(&oracle);
+    }
+
+    pub fn get_predicate_oracles(&self) -> Vec<AccountId> {
+        self.predicate_oracles.to_vec()
+    }
+
+    pub fn claim_protocol_fees(&mut self, token: AccountId) -> Promise {
+        self._apply_pending_protocol_fee();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.protocol_fee_receiver,
+            "Only fee receiver can claim"
+        );
+        let amount = self.protocol_fees.insert(&token, &0).unwrap_or(0);
+        assert!(amount > 0, "Nothing to claim");
+        
+        self._ft_transfer(&token, &self.protocol_fee_receiver, amount, "Protocol fee claim").then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_protocol_fees_claimed".to_string(),
+                serde_json::json!({
+                    "token": token,
+                    "amount": U128(amount),
+                }).to_string().as_bytes().to_vec(),
+                0,
+                GAS_FOR_CALLBACK,
+            )
+        )
+    }
+
+    #[private]
+    pub fn on_protocol_fees_claimed(&mut self, token: AccountId, amount: U128) {
+        if !matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            let accrued = self.protocol_fees.get(&token).unwrap_or(0);
+            self.protocol_fees.insert(&token, &(accrued + amount.0));
+        }
+    }
+
+    pub fn claim_integrator_fees(&mut self, token: AccountId) -> Promise {
+        let integrator = env::predecessor_account_id();
+        let amount = self.integrator_fees.insert(&(integrator.clone(), token.clone()), &0).unwrap_or(0);
+        assert!(amount > 0, "Nothing to claim");
+        
+        self._ft_transfer(&token, &integrator, amount, "Integrator fee claim").then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_integrator_fees_claimed".to_string(),
+                serde_json::json!({
+                    "integrator": integrator,
+                    "token": token,
+                    "amount": U128(amount),
+                }).to_string().as_bytes().to_vec(),
+                0,
+                GAS_FOR_CALLBACK,
+            )
+        )
+    }
+
+    #[private]
+    pub fn on_integrator_fees_claimed(&mut self, integrator: AccountId, token: AccountId, amount: U128) {
+        if !matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            let key = (integrator, token);
+            let accrued = self.integrator_fees.get(&key).unwrap_or(0);
+            self.integrator_fees.insert(&key, &(accrued + amount.0));
+        }
+    }
+
+    /// Schedules a new fee receiver, subject to the same notice period as a fee change.
+    pub fn schedule_protocol_fee_receiver(&mut self, receiver: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can update fees"
+        );
+        self._schedule_protocol_fee_change(Some(receiver), None);
+    }
+
+    /// Schedules a new protocol fee which applies once the notice period has passed.
+    pub fn schedule_protocol_fee(&mut self, basis_points: u32) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can update fees"
+        );
+        assert!(basis_points <= MAX_PROTOCOL_FEE_BASIS_POINTS, "Invalid fee");
+        self._schedule_protocol_fee_change(None, Some(basis_points));
+    }
+
+    pub fn cancel_protocol_fee_change(&mut self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can update fees"
+        );
+        self._apply_pending_protocol_fee();
+        assert!(self.pending_protocol_fee.take().is_some(), "No pending fee change");
+    }
+
+    pub fn set_surplus_shares(&mut self, maker_basis_points: u32, protocol_basis_points: u32) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can update fees"
+        );
+        assert!(
+            maker_basis_points + protocol_basis_points <= BASIS_POINTS,
+            "Invalid surplus shares"
+        );
+        self.surplus_shares = SurplusShares {
+            maker_basis_points,
+            protocol_basis_points,
+        };
+    }
+
+    pub fn get_surplus_shares(&self) -> SurplusShares {
+        self.surplus_shares.clone()
+    }
+
+    pub fn get_protocol_fee_config(&self) -> ProtocolFeeConfig {
+        match &self.pending_protocol_fee {
+            Some(pending) if env::block_timestamp() >= pending.effective_at => ProtocolFeeConfig {
+                receiver: pending.receiver.clone(),
+                basis_points: pending.basis_points,
+                pending: None,
+            },
+            pending => ProtocolFeeConfig {
+                receiver: self.protocol_fee_receiver.clone(),
+                basis_points: self.protocol_fee_basis_points,
+                pending: pending.clone(),
+            },
+        }
+    }
+
+    pub fn get_accrued_protocol_fees(&self, token: AccountId) -> U128 {
+        U128(self.protocol_fees.get(&token).unwrap_or(0))
+    }
+
+    pub fn get_accrued_integrator_fees(&self, integrator: AccountId, token: AccountId) -> U128 {
+        U128(self.integrator_fees.get(&(integrator, token)).unwrap_or(0))
+    }
+
+    pub fn get_balance(&self, account_id: AccountId, token: AccountId) -> U128 {
+        U128(self._balance_of(&account_id, &token))
+    }
+
     pub fn get_order(&self, order_hash: [u8; 32]) -> Option<OrderState> {
         self.orders.get(&order_hash)
     }
 
+    pub fn get_order_amendments(&self, order_hash: [u8; 32]) -> Vec<OrderAmendment> {
+        self.orders
+            .get(&order_hash)
+            .map(|order_state| order_state.amendments)
+            .unwrap_or_default()
+    }
+
+    /// Most recent settled fills of an order, oldest first.
+    pub fn get_fill_history(&self, order_hash: [u8; 32]) -> Vec<FillRecord> {
+        self.fill_history.get(&order_hash).unwrap_or_default()
+    }
+
+    pub fn get_pair_stats(&self, maker_asset: AccountId, taker_asset: AccountId) -> PairStats {
+        self.pair_stats.get(&(maker_asset, taker_asset)).unwrap_or_default()
+    }
+
+    pub fn get_twap_schedule(&self, order_hash: [u8; 32]) -> Vec<TwapSlice> {
+        let order_state = self.orders.get(&order_hash).expect("Order not found");
+        let twap = order_state.twap.as_ref().expect("Not a TWAP order");
+        let total = order_state.order.making_amount.0;
+        let now = env::block_timestamp();
+        
+        (0..twap.slice_count)
+            .map(|index| {
+                let unlocked_before = mul_div(total, index as u128, twap.slice_count as u128, false);
+                let unlocked_after = mul_div(total, index as u128 + 1, twap.slice_count as u128, false);
+                TwapSlice {
+                    index,
+                    unlock_time: twap.unlock_time(index),
+                    making_amount: U128(unlocked_after - unlocked_before),
+                    auction: order_state.auction.as_ref().map(|auction| twap.slice_auction(auction, index)),
+                    is_unlocked: now >= twap.unlock_time(index),
+                }
+            })
+            .collect()
+    }
+
+    /// Making amount that can be filled right now, after TWAP and recurring limits.
+    pub fn get_fillable_amount(&self, order_hash: [u8; 32]) -> U128 {
+        let order_state = self.orders.get(&order_hash).expect("Order not found");
+        U128(order_state.fillable_making_amount(env::block_timestamp()))
+    }
+
+    pub fn get_recurring_period(&self, order_hash: [u8; 32]) -> RecurringPeriod {
+        let order_state = self.orders.get(&order_hash).expect("Order not found");
+        let recurring = order_state.recurring.as_ref().expect("Not a recurring order");
+        let now = env::block_timestamp();
+        let period_index = recurring.period_index(now);
+        
+        RecurringPeriod {
+            period_index,
+            period_start: recurring.period_start(period_index),
+            period_end: recurring.period_start(period_index + 1),
+            available_making_amount: U128(order_state.fillable_making_amount(now)),
+            auction: order_state
+                .auction
+                .as_ref()
+                .map(|auction| recurring.period_auction(auction, period_index)),
+        }
+    }
+
     pub fn get_remaining_amounts(&self, order_hash: [u8; 32]) -> (U128, U128) {
         if let Some(order_state) = self.orders.get(&order_hash) {
-            let remaining_making = order_state.order.making_amount.0 - order_state.filled_making_amount;
-            let remaining_taking = order_state.order.taking_amount.0 - order_state.filled_taking_amount;
-            (U128(remaining_making), U128(remaining_taking))
+            (
+                U128(order_state.remaining_making_amount()),
+                U128(order_state.remaining_taking_amount()),
+            )
         } else {
             (U128(0), U128(0))
         }
     }
+
+    /// All orders ever created by `maker`, including filled and cancelled ones.
+    pub fn get_orders_by_maker(
+        &self,
+        maker: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<OrderView> {
+        match self.orders_by_maker.get(&maker) {
+            Some(hashes) => self._paginate(hashes.iter(), from_index, limit),
+            None => vec![],
+        }
+    }
+
+    /// Live orders for the given asset pair: not cancelled or fully filled.
+    /// Expired orders stay listed, flagged `is_expired`, until pruned.
+    pub fn get_active_orders_by_pair(
+        &self,
+        maker_asset: AccountId,
+        taker_asset: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<OrderView> {
+        match self.active_orders_by_pair.get(&(maker_asset, taker_asset)) {
+            Some(hashes) => self._paginate(hashes.iter(), from_index, limit),
+            None => vec![],
+        }
+    }
+
+    /// Live orders, oldest first: not cancelled or fully filled. Expired orders
+    /// stay listed, flagged `is_expired`, until pruned.
+    pub fn get_open_orders(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<OrderView> {
+        self._paginate(
+            self.open_orders.iter().map(|((_, order_hash), _)| order_hash),
+            from_index,
+            limit,
+        )
+    }
+
+    pub fn get_open_orders_count(&self) -> u64 {
+        self.open_orders.len()
+    }
+}
+
+impl FusionOrderContract {
+    fn _create_order(
+        &mut self,
+        order: FusionOrder,
+        auction: Option<AuctionDetails>,
+        twap: Option<TwapSchedule>,
+        recurring: Option<RecurringSchedule>,
+    ) -> [u8; 32] {
+        let maker = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        assert_eq!(order.maker, maker, "Only maker can create order");
+        
+        if let Some(integrator_fee) = &order.integrator_fee {
+            assert!(
+                integrator_fee.basis_points <= MAX_INTEGRATOR_FEE_BASIS_POINTS,
+                "Integrator fee too high"
+            );
+        }
+        assert!(
+            order.exclusivity_end.is_none() || order.resolver.is_some(),
+            "Exclusivity requires resolver"
+        );
+        if let Some(predicate) = &order.predicate {
+            predicate::validate(predicate, &self.predicate_oracles);
+        }
+        
+        let order_hash = order.hash();
+        assert!(self.orders.get(&order_hash).is_none(), "Order exists");
+        
+        // The full making amount stays reserved in the contract until filled or cancelled
+        self._withdraw(&maker, &order.maker_asset, order.making_amount.0);
+        
+        let mut order_state = OrderState {
+            order,
+            filled_making_amount: 0,
+            filled_taking_amount: 0,
+            is_cancelled: false,
+            auction,
+            created_at: env::block_timestamp(),
+            fills_in_flight: 0,
+            storage_deposit: 0,
+            amendments: Vec::new(),
+            amended_terms: None,
+            settlement: None,
+            twap,
+            recurring,
+            current_period: 0,
+            current_period_filled: 0,
+        };
+        
+        self._index_order(&order_hash, &order_state);
+        self._open_fill_entries(&order_state.order);
+        self.orders.insert(&order_hash, &order_state);
+        
+        let reserved = MAX_FILL_HISTORY as u64 * FILL_RECORD_STORAGE;
+        order_state.storage_deposit = self._charge_storage(initial_storage_usage, reserved, &maker);
+        self.orders.insert(&order_hash, &order_state);
+        order_hash
+    }
+
+    /// Opens the pair stats and fee entries a fill of `order` writes to, so the
+    /// maker pays for them here instead of a settling fill growing storage
+    /// nobody paid for. Entries are shared and kept once opened.
+    fn _open_fill_entries(&mut self, order: &FusionOrder) {
+        let pair = (order.maker_asset.clone(), order.taker_asset.clone());
+        if self.pair_stats.get(&pair).is_none() {
+            self.pair_stats.insert(&pair, &PairStats::default());
+        }
+        for token in [&order.maker_asset, &order.taker_asset] {
+            if self.protocol_fees.get(token).is_none() {
+                self.protocol_fees.insert(token, &0);
+            }
+        }
+        if let Some(fee) = &order.integrator_fee {
+            let key = (fee.receiver.clone(), order.maker_asset.clone());
+            if self.integrator_fees.get(&key).is_none() {
+                self.integrator_fees.insert(&key, &0);
+            }
+        }
+    }
+
+    /// Takes the cost of the storage added since `initial_storage_usage`, plus
+    /// `reserved_bytes` for later growth, out of the attached deposit and
+    /// refunds the rest to `payer`.
+    fn _charge_storage(&self, initial_storage_usage: u64, reserved_bytes: u64, payer: &AccountId) -> u128 {
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage) + reserved_bytes;
+        let storage_cost = storage_used as u128 * env::storage_byte_cost();
+        let attached_deposit = env::attached_deposit();
+        assert!(attached_deposit >= storage_cost, "Insufficient storage deposit");
+        
+        if attached_deposit > storage_cost {
+            Promise::new(payer.clone()).transfer(attached_deposit - storage_cost);
+        }
+        storage_cost
+    }
+
+    /// Starts a fill, first reading any remote values the order's predicate
+    /// depends on. `transfer_amount` is set when the taker's tokens arrived
+    /// through `ft_transfer_call` and have not been credited yet.
+    fn _start_fill(
+        &mut self,
+        taker: AccountId,
+        order_hash: [u8; 32],
+        request: FillRequest,
+        transfer_amount: Option<u128>,
+    ) -> Promise {
+        let order_state = self.orders.get(&order_hash).expect("Order not found");
+        if let Some(predicate) = &order_state.order.predicate {
+            predicate::validate(predicate, &self.predicate_oracles);
+        }
+        let remote_calls = order_state
+            .order
+            .predicate
+            .as_ref()
+            .map(predicate::remote_calls)
+            .unwrap_or_default();
+        
+        if remote_calls.is_empty() {
+            return self._execute_fill(&taker, order_hash, request, transfer_amount, &[]);
+        }
+        
+        predicate::query(remote_calls).then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_predicate_checked".to_string(),
+                serde_json::json!({
+                    "taker": taker,
+                    "order_hash": order_hash,
+                    "request": request,
+                    "transfer_amount": transfer_amount.map(U128),
+                }).to_string().as_bytes().to_vec(),
+                0,
+                GAS_FOR_PREDICATE_CALLBACK,
+            )
+        )
+    }
+
+    fn _execute_fill(
+        &mut self,
+        taker: &AccountId,
+        order_hash: [u8; 32],
+        request: FillRequest,
+        transfer_amount: Option<u128>,
+        predicate_results: &[Option<Vec<u8>>],
+    ) -> Promise {
+        let taker_asset = self.orders.get(&order_hash).expect("Order not found").order.taker_asset;
+        if let Some(amount) = transfer_amount {
+            self._deposit(taker, &taker_asset, amount);
+        }
+        
+        let receipt = self._fill_order(taker, order_hash, request, predicate_results);
+        
+        // The fill draws on this transfer first and on any existing deposit after it
+        let unused = transfer_amount.map_or(0, |amount| amount.saturating_sub(receipt.actual_taking_amount.0));
+        self._withdraw(taker, &taker_asset, unused);
+        self._settle_fill(receipt, unused, transfer_amount)
+    }
+
+    /// Settles a fill against the contract's internal balances. The payouts are
+    /// sent afterwards by `_settle_fill`.
+    fn _fill_order(
+        &mut self,
+        taker: &AccountId,
+        order_hash: [u8; 32],
+        request: FillRequest,
+        predicate_results: &[Option<Vec<u8>>],
+    ) -> FillReceipt {
+        let FillRequest { fill, resolver_fee, limits } = request;
+        let mut order_state = self.orders.get(&order_hash).expect("Order not found");
+        
+        if let Some(deadline) = limits.deadline {
+            assert!(env::block_timestamp() <= deadline, "Fill deadline passed");
+        }
+        assert!(!order_state.is_cancelled, "Order cancelled");
+        assert!(!order_state.is_expired(env::block_timestamp()), "Order expired");
+        self._assert_can_fill(&order_hash, &order_state.order, taker);
+        
+        if let Some(predicate) = &order_state.order.predicate {
+            let maker_nonce = self.maker_nonces.get(&order_state.order.maker).unwrap_or(0);
+            assert!(
+                predicate::evaluate(predicate, maker_nonce, predicate_results),
+                "Predicate not satisfied"
+            );
+        }
+        
+        let (making_amount, taking_amount) = self._resolve_fill_amounts(&order_state, fill);
+        assert!(making_amount > 0 && taking_amount > 0, "Fill amount too small");
+        
+        assert!(making_amount <= order_state.remaining_making_amount(), "Exceeds available");
+        assert!(
+            making_amount <= order_state.fillable_making_amount(env::block_timestamp()),
+            "Exceeds fillable amount"
+        );
+        assert!(taking_amount <= order_state.remaining_taking_amount(), "Exceeds required");
+        let (filled_making_amount, filled_taking_amount) = order_state.terms_filled_amounts();
+        assert!(
+            order_state.effective_order().is_price_respected(
+                filled_making_amount + making_amount,
+                filled_taking_amount + taking_amount,
+            ),
+            "Fill below order price"
+        );
+        
+        // The auction bump is rounded up so it is never lost on small fills. It is
+        // the surplus over the maker's base rate and is shared out per `surplus_shares`.
+        let rate_bump = self._current_rate_bump(&order_hash, &order_state);
+        let surplus = mul_div(taking_amount, rate_bump as u128, BASIS_POINTS as u128, true);
+        let maker_surplus = mul_div(
+            surplus,
+            self.surplus_shares.maker_basis_points as u128,
+            BASIS_POINTS as u128,
+            true,
+        );
+        let protocol_surplus = mul_div(
+            surplus,
+            self.surplus_shares.protocol_basis_points as u128,
+            BASIS_POINTS as u128,
+            false,
+        );
+        let mut actual_taking_amount = taking_amount + maker_surplus + protocol_surplus;
+        let mut resolver_fee_amount = 0;
+        
+        if let Some(resolver_fee) = resolver_fee {
+            if let Some(resolver) = &order_state.order.resolver {
+                assert_eq!(resolver_fee.receiver, *resolver, "Invalid resolver");
+                assert!(
+                    self.resolver_whitelist.get(resolver).unwrap_or(false),
+                    "Resolver not whitelisted"
+                );
+                resolver_fee_amount = resolver_fee.amount.0;
+                actual_taking_amount += resolver_fee_amount;
+            }
+        }
+        
+        self._apply_pending_protocol_fee();
+        let protocol_fee = (making_amount * self.protocol_fee_basis_points as u128) / BASIS_POINTS as u128;
+        let integrator_fee = order_state.order.integrator_fee.as_ref().map_or(0, |fee| {
+            (making_amount * fee.basis_points as u128) / BASIS_POINTS as u128
+        });
+        let maker_receives = actual_taking_amount - protocol_surplus;
+        let taker_receives = making_amount - protocol_fee - integrator_fee;
+        
+        if let Some(max_taking_amount) = limits.max_taking_amount {
+            assert!(actual_taking_amount <= max_taking_amount.0, "Taking amount exceeds limit");
+        }
+        if let Some(min_making_amount) = limits.min_making_amount {
+            assert!(taker_receives >= min_making_amount.0, "Making amount below limit");
+        }
+        
+        // Taker pays from their deposit, maker's side comes out of the order reservation
+        self._withdraw(taker, &order_state.order.taker_asset, actual_taking_amount);
+        
+        if surplus > 0 {
+            emit_event("surplus", serde_json::json!({
+                "order_hash": order_hash,
+                "taker": taker,
+                "token": order_state.order.taker_asset,
+                "surplus": U128(surplus),
+                "maker_surplus": U128(maker_surplus),
+                "protocol_surplus": U128(protocol_surplus),
+            }));
+        }
+        
+        if let Some(fee) = order_state.order.integrator_fee.as_ref().filter(|_| integrator_fee > 0) {
+            emit_event("integrator_fee", serde_json::json!({
+                "order_hash": order_hash,
+                "integrator": fee.receiver,
+                "token": order_state.order.maker_asset,
+                "amount": U128(integrator_fee),
+            }));
+        }
+        
+        order_state.filled_making_amount += making_amount;
+        order_state.filled_taking_amount += taking_amount;
+        order_state.fills_in_flight += 1;
+        self._track_winner_fill(&order_hash, taker, making_amount, false);
+        
+        if let Some(recurring) = &order_state.recurring {
+            let period_index = recurring.period_index(env::block_timestamp());
+            if period_index != order_state.current_period {
+                order_state.current_period = period_index;
+                order_state.current_period_filled = 0;
+            }
+            order_state.current_period_filled += making_amount;
+        }
+        
+        if !order_state.is_active() {
+            self._deactivate_order(&order_hash, &order_state);
+        }
+        
+        self.orders.insert(&order_hash, &order_state);
+        
+        let receipt = FillReceipt {
+            order_hash,
+            taker: taker.clone(),
+            making_amount: U128(making_amount),
+            taking_amount: U128(taking_amount),
+            actual_taking_amount: U128(actual_taking_amount),
+            rate_bump,
+            resolver_fee: U128(resolver_fee_amount),
+            protocol_fee: U128(protocol_fee),
+            integrator_fee: U128(integrator_fee),
+            surplus: U128(surplus),
+            maker_surplus: U128(maker_surplus),
+            protocol_surplus: U128(protocol_surplus),
+            maker_receives: U128(maker_receives),
+            taker_receives: U128(taker_receives),
+            filled_at: env::block_timestamp(),
+        };
+        emit_event("order_filled", serde_json::to_value(&receipt).unwrap());
+        receipt
+    }
+
+    /// Orders with an exclusivity window can only be filled by their named
+    /// resolver until it ends, and by any whitelisted resolver after that. A
+    /// sealed-bid round blocks fills until its winner's window, which overrides both.
+    fn _assert_can_fill(&self, order_hash: &[u8; 32], order: &FusionOrder, taker: &AccountId) {
+        if let Some(round) = self.bidding_rounds.get(order_hash) {
+            let now = env::block_timestamp();
+            assert!(!round.is_open(now), "Bidding in progress");
+            if let Some(bid) = round.exclusive_bid(now) {
+                assert_eq!(bid.resolver, *taker, "Exclusive bid window");
+                return;
+            }
+        }
+        
+        if let Some(exclusivity_end) = order.exclusivity_end {
+            if env::block_timestamp() < exclusivity_end {
+                assert_eq!(order.resolver.as_ref(), Some(taker), "Exclusive resolver window");
+            } else {
+                assert!(
+                    self.resolver_whitelist.get(taker).unwrap_or(false),
+                    "Resolver not whitelisted"
+                );
+            }
+        }
+    }
+
+    /// The winning bid's bump replaces the auction while its window runs.
+    fn _current_rate_bump(&self, order_hash: &[u8; 32], order_state: &OrderState) -> u32 {
+        let now = env::block_timestamp();
+        self.bidding_rounds
+            .get(order_hash)
+            .and_then(|round| round.exclusive_bid(now).and_then(|bid| bid.rate_bump))
+            .unwrap_or_else(|| order_state.rate_bump(now))
+    }
+
+    /// Counts what a round's winner has filled, which decides if they keep their bond.
+    fn _track_winner_fill(&mut self, order_hash: &[u8; 32], taker: &AccountId, making_amount: u128, reverted: bool) {
+        if let Some(mut round) = self.bidding_rounds.get(order_hash) {
+            let is_winner = round.winner.is_some_and(|index| round.bids[index].resolver == *taker);
+            if is_winner && !round.is_settled {
+                round.winner_filled = if reverted {
+                    U128(round.winner_filled.0.saturating_sub(making_amount))
+                } else {
+                    U128(round.winner_filled.0 + making_amount)
+                };
+                self.bidding_rounds.insert(order_hash, &round);
+            }
+        }
+    }
+
+    fn _resolve_fill_amounts(&self, order_state: &OrderState, fill: FillAmount) -> (u128, u128) {
+        let order = order_state.effective_order();
+        let (filled_making_amount, filled_taking_amount) = order_state.terms_filled_amounts();
+        match fill {
+            FillAmount::Exact(U128(making_amount), U128(taking_amount)) => {
+                assert!(
+                    order.is_valid_partial_fill(making_amount, taking_amount),
+                    "Invalid fill amounts"
+                );
+                (making_amount, taking_amount)
+            }
+            FillAmount::Making(U128(making_amount)) => {
+                let making_amount =
+                    making_amount.min(order_state.fillable_making_amount(env::block_timestamp()));
+                let taking_amount =
+                    order.taking_amount_for(filled_making_amount, filled_taking_amount, making_amount);
+                (making_amount, taking_amount)
+            }
+            FillAmount::Taking(U128(taking_amount)) => {
+                // Capped at the taking amount of what is fillable now, as for `Making`
+                let fillable_making_amount = order_state.fillable_making_amount(env::block_timestamp());
+                let taking_amount = taking_amount.min(order.taking_amount_for(
+                    filled_making_amount,
+                    filled_taking_amount,
+                    fillable_making_amount,
+                ));
+                let making_amount =
+                    order.making_amount_for(filled_making_amount, filled_taking_amount, taking_amount);
+                (making_amount, taking_amount)
+            }
+        }
+    }
+
+    /// Pays the taker first and only pays the maker once that transfer is known
+    /// to have succeeded, so a failed payout can still be rolled back.
+    fn _settle_fill(&self, receipt: FillReceipt, unused: u128, transfer_amount: Option<u128>) -> Promise {
+        let order_state = self.orders.get(&receipt.order_hash).expect("Order not found");
+        
+        self._ft_transfer(
+            &order_state.order.maker_asset,
+            &receipt.taker,
+            receipt.taker_receives.0,
+            "Fusion fill",
+        ).then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_fill_settled".to_string(),
+                serde_json::json!({
+                    "receipt": receipt,
+                    "unused": U128(unused),
+                    "transfer_amount": transfer_amount.map(U128),
+                }).to_string().as_bytes().to_vec(),
+                0,
+                GAS_FOR_FILL_CALLBACK,
+            )
+        )
+    }
+
+    fn _revert_fill(&mut self, receipt: &FillReceipt) {
+        let mut order_state = self.orders.get(&receipt.order_hash).expect("Order not found");
+        let was_active = order_state.is_active();
+        
+        order_state.filled_making_amount -= receipt.making_amount.0;
+        order_state.filled_taking_amount -= receipt.taking_amount.0;
+        self._track_winner_fill(&receipt.order_hash, &receipt.taker, receipt.making_amount.0, true);
+        
+        // Give the budget back if the fill's period is still the current one
+        if let Some(recurring) = &order_state.recurring {
+            if recurring.period_index(receipt.filled_at) == order_state.current_period {
+                order_state.current_period_filled =
+                    order_state.current_period_filled.saturating_sub(receipt.making_amount.0);
+            }
+        }
+        
+        // Fees are only accrued once a fill settles, so there is nothing to take back
+        if order_state.is_cancelled {
+            // The reservation was already released on cancel, so return this part directly
+            self._deposit(&order_state.order.maker, &order_state.order.maker_asset, receipt.making_amount.0);
+            if let Some(settlement) = order_state.settlement.as_mut() {
+                settlement.filled_making_amount.0 -= receipt.making_amount.0;
+                settlement.filled_taking_amount.0 -= receipt.taking_amount.0;
+                settlement.released_making_amount.0 += receipt.making_amount.0;
+            }
+        } else if !was_active {
+            self._index_order(&receipt.order_hash, &order_state);
+        }
+        
+        self.orders.insert(&receipt.order_hash, &order_state);
+    }
+
+    /// Closes out a fill once its payout is known: a settled fill accrues its
+    /// fees, is added to the history and may complete the order.
+    fn _complete_fill(&mut self, receipt: &FillReceipt, is_settled: bool) -> OrderState {
+        let mut order_state = self.orders.get(&receipt.order_hash).expect("Order not found");
+        order_state.fills_in_flight -= 1;
+        
+        if is_settled && order_state.fills_in_flight == 0 && order_state.remaining_making_amount() == 0 {
+            order_state.settlement = Some(OrderSettlement {
+                reason: SettlementReason::Filled,
+                filled_making_amount: U128(order_state.filled_making_amount),
+                filled_taking_amount: U128(order_state.filled_taking_amount),
+                released_making_amount: U128(0),
+                settled_at: env::block_timestamp(),
+            });
+        }
+        self.orders.insert(&receipt.order_hash, &order_state);
+        
+        if is_settled {
+            self._accrue_fees(&order_state.order, receipt);
+            self._record_fill(&order_state.order, receipt);
+        }
+        order_state
+    }
+
+    /// Fees of a fill become claimable only here, never while its payout is in flight.
+    fn _accrue_fees(&mut self, order: &FusionOrder, receipt: &FillReceipt) {
+        if receipt.protocol_fee.0 > 0 {
+            let accrued = self.protocol_fees.get(&order.maker_asset).unwrap_or(0);
+            self.protocol_fees.insert(&order.maker_asset, &(accrued + receipt.protocol_fee.0));
+        }
+        
+        if receipt.protocol_surplus.0 > 0 {
+            let accrued = self.protocol_fees.get(&order.taker_asset).unwrap_or(0);
+            self.protocol_fees.insert(&order.taker_asset, &(accrued + receipt.protocol_surplus.0));
+        }
+        
+        if let Some(fee) = order.integrator_fee.as_ref().filter(|_| receipt.integrator_fee.0 > 0) {
+            let key = (fee.receiver.clone(), order.maker_asset.clone());
+            let accrued = self.integrator_fees.get(&key).unwrap_or(0);
+            self.integrator_fees.insert(&key, &(accrued + receipt.integrator_fee.0));
+        }
+    }
+
+    fn _record_fill(&mut self, order: &FusionOrder, receipt: &FillReceipt) {
+        let mut history = self.fill_history.get(&receipt.order_hash).unwrap_or_default();
+        if history.len() == MAX_FILL_HISTORY {
+            history.remove(0);
+        }
+        history.push(FillRecord {
+            taker: receipt.taker.clone(),
+            making_amount: receipt.making_amount,
+            taking_amount: receipt.taking_amount,
+            actual_taking_amount: receipt.actual_taking_amount,
+            rate_bump: receipt.rate_bump,
+            resolver_fee: receipt.resolver_fee,
+            protocol_fee: receipt.protocol_fee,
+            integrator_fee: receipt.integrator_fee,
+            protocol_surplus: receipt.protocol_surplus,
+            filled_at: receipt.filled_at,
+        });
+        self.fill_history.insert(&receipt.order_hash, &history);
+        
+        let pair = (order.maker_asset.clone(), order.taker_asset.clone());
+        let stats = self.pair_stats.get(&pair).unwrap_or_default();
+        self.pair_stats.insert(&pair, &PairStats {
+            making_volume: U128(stats.making_volume.0 + receipt.making_amount.0),
+            taking_volume: U128(stats.taking_volume.0 + receipt.actual_taking_amount.0),
+            fill_count: stats.fill_count + 1,
+        });
+    }
+
+    /// Pays for the storage added since `initial_storage_usage` out of the
+    /// account's prepaid storage balance.
+    fn _charge_storage_balance(&mut self, account_id: &AccountId, initial_storage_usage: u64) {
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+        if storage_used == 0 {
+            return;
+        }
+        let storage_cost = storage_used as u128 * env::storage_byte_cost();
+        let balance = self.storage_balances.get(account_id).unwrap_or(0);
+        assert!(balance >= storage_cost, "Insufficient storage balance");
+        self.storage_balances.insert(account_id, &(balance - storage_cost));
+    }
+
+    fn _balance_of(&self, account_id: &AccountId, token: &AccountId) -> u128 {
+        self.balances.get(&(account_id.clone(), token.clone())).unwrap_or(0)
+    }
+
+    fn _deposit(&mut self, account_id: &AccountId, token: &AccountId, amount: u128) {
+        if amount == 0 {
+            return;
+        }
+        let key = (account_id.clone(), token.clone());
+        let balance = self.balances.get(&key).unwrap_or(0);
+        self.balances.insert(&key, &(balance + amount));
+    }
+
+    /// Credits a balance, paying for the entry if this opens it out of `payer`'s
+    /// prepaid storage.
+    fn _deposit_with_storage(&mut self, payer: &AccountId, account_id: &AccountId, token: &AccountId, amount: u128) {
+        let initial_storage_usage = env::storage_usage();
+        self._deposit(account_id, token, amount);
+        self._charge_storage_balance(payer, initial_storage_usage);
+    }
+
+    fn _withdraw(&mut self, account_id: &AccountId, token: &AccountId, amount: u128) {
+        if amount == 0 {
+            return;
+        }
+        let key = (account_id.clone(), token.clone());
+        let balance = self.balances.get(&key).unwrap_or(0);
+        assert!(balance >= amount, "Insufficient balance");
+        if balance == amount {
+            self.balances.remove(&key);
+        } else {
+            self.balances.insert(&key, &(balance - amount));
+        }
+    }
+
+    fn _ft_transfer(&self, token: &AccountId, receiver: &AccountId, amount: u128, memo: &str) -> Promise {
+        Promise::new(token.clone()).function_call(
+            "ft_transfer".to_string(),
+            format!(
+                r#"{{"receiver_id":"{}","amount":"{}","memo":"{}"}}"#,
+                receiver, amount, memo
+            )
+            .as_bytes()
+            .to_vec(),
+            1,
+            GAS_FOR_FT_TRANSFER,
+        )
+    }
+
+    fn _apply_pending_protocol_fee(&mut self) {
+        if let Some(pending) = &self.pending_protocol_fee {
+            if env::block_timestamp() >= pending.effective_at {
+                self.protocol_fee_receiver = pending.receiver.clone();
+                self.protocol_fee_basis_points = pending.basis_points;
+                self.pending_protocol_fee = None;
+            }
+        }
+    }
+
+    /// Merges a receiver or fee change into the pending one. The whole change
+    /// restarts the notice period, so neither part can take effect early.
+    fn _schedule_protocol_fee_change(&mut self, receiver: Option<AccountId>, basis_points: Option<u32>) {
+        self._apply_pending_protocol_fee();
+        let (current_receiver, current_basis_points) = match self.pending_protocol_fee.take() {
+            Some(pending) => (pending.receiver, pending.basis_points),
+            None => (self.protocol_fee_receiver.clone(), self.protocol_fee_basis_points),
+        };
+        self.pending_protocol_fee = Some(PendingProtocolFee {
+            receiver: receiver.unwrap_or(current_receiver),
+            basis_points: basis_points.unwrap_or(current_basis_points),
+            effective_at: env::block_timestamp() + PROTOCOL_FEE_NOTICE_PERIOD,
+        });
+    }
+
+    fn _paginate(
+        &self,
+        hashes: impl Iterator<Item = [u8; 32]>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<OrderView> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+        hashes
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit as usize)
+            .filter_map(|order_hash| self._order_view(order_hash))
+            .collect()
+    }
+
+    fn _order_view(&self, order_hash: [u8; 32]) -> Option<OrderView> {
+        let order_state = self.orders.get(&order_hash)?;
+        let remaining_taking = order_state.remaining_taking_amount();
+        let rate_bump = self._current_rate_bump(&order_hash, &order_state);
+        let current_taking_amount =
+            remaining_taking + (remaining_taking * rate_bump as u128) / BASIS_POINTS as u128;
+        
+        Some(OrderView {
+            order_hash,
+            remaining_making_amount: U128(order_state.remaining_making_amount()),
+            remaining_taking_amount: U128(remaining_taking),
+            rate_bump,
+            current_taking_amount: U128(current_taking_amount),
+            created_at: order_state.created_at,
+            is_cancelled: order_state.is_cancelled,
+            is_expired: order_state.is_expired(env::block_timestamp()),
+            auction: order_state.auction,
+            amended_terms: order_state.amended_terms,
+            order: order_state.order,
+        })
+    }
+
+    fn _index_order(&mut self, order_hash: &[u8; 32], order_state: &OrderState) {
+        let maker = &order_state.order.maker;
+        let mut maker_orders = self.orders_by_maker.get(maker).unwrap_or_else(|| {
+            UnorderedSet::new([b"m".as_slice(), &env::sha256(maker.as_bytes())].concat())
+        });
+        maker_orders.insert(order_hash);
+        self.orders_by_maker.insert(maker, &maker_orders);
+        
+        let pair = (order_state.order.maker_asset.clone(), order_state.order.taker_asset.clone());
+        let mut pair_orders = self.active_orders_by_pair.get(&pair).unwrap_or_else(|| {
+            let pair_id = format!("{}:{}", pair.0, pair.1);
+            UnorderedSet::new([b"p".as_slice(), &env::sha256(pair_id.as_bytes())].concat())
+        });
+        pair_orders.insert(order_hash);
+        self.active_orders_by_pair.insert(&pair, &pair_orders);
+        
+        self.open_orders.insert(&(order_state.created_at, *order_hash), &());
+    }
+
+    fn _deactivate_order(&mut self, order_hash: &[u8; 32], order_state: &OrderState) {
+        let pair = (order_state.order.maker_asset.clone(), order_state.order.taker_asset.clone());
+        if let Some(mut pair_orders) = self.active_orders_by_pair.get(&pair) {
+            pair_orders.remove(order_hash);
+            self.active_orders_by_pair.insert(&pair, &pair_orders);
+        }
+        
+        self.open_orders.remove(&(order_state.created_at, *order_hash));
+    }
+}
+
+/// Logs a NEP-297 event for indexers.
+fn emit_event(event: &str, data: serde_json::Value) {
+    env::log_str(&format!(
+        "EVENT_JSON:{}",
+        serde_json::json!({
+            "standard": "fusion-order",
+            "version": "1.0.0",
+            "event": event,
+            "data": [data],
+        })
+    ));
 }
\ No newline at end of file
@@ -0,0 +1,125 @@
+use escrow::{Condition, Predicate};
+use near_sdk::json_types::U128;
+use near_sdk::collections::UnorderedSet;
+use near_sdk::{env, Gas, AccountId, Promise};
+
+const GAS_FOR_PREDICATE_VIEW: Gas = Gas(5_000_000_000_000);
+const MAX_PREDICATE_CONDITIONS: usize = 8;
+const MAX_PREDICATE_CALLS: usize = 4;
+/// View methods a predicate may read. Anything else could change state on the
+/// target, which is then called with this contract as the predecessor.
+const VIEW_METHODS: [&str; 3] = ["get_value", "get_status", "is_paused"];
+
+/// A view call a predicate needs answered before the order can be filled.
+pub struct RemoteCall {
+    pub contract_id: AccountId,
+    pub method_name: String,
+    pub args: Vec<u8>,
+}
+
+/// Checks the predicate's shape and that every remote read goes to an
+/// allowlisted oracle through a known view method.
+pub fn validate(predicate: &Predicate, oracles: &UnorderedSet<AccountId>) {
+    let conditions = conditions(predicate);
+    assert!(!conditions.is_empty(), "Empty predicate");
+    assert!(conditions.len() <= MAX_PREDICATE_CONDITIONS, "Predicate too complex");
+
+    let calls = remote_calls(predicate);
+    assert!(calls.len() <= MAX_PREDICATE_CALLS, "Predicate too complex");
+    for call in calls {
+        assert_ne!(call.contract_id, env::current_account_id(), "Predicate cannot call this contract");
+        assert!(oracles.contains(&call.contract_id), "Predicate target not allowed");
+        assert!(VIEW_METHODS.contains(&call.method_name.as_str()), "Predicate method not allowed");
+    }
+}
+
+fn conditions(predicate: &Predicate) -> &[Condition] {
+    match predicate {
+        Predicate::And { conditions } | Predicate::Or { conditions } => conditions,
+    }
+}
+
+/// Remote reads in the order `evaluate` consumes their results.
+pub fn remote_calls(predicate: &Predicate) -> Vec<RemoteCall> {
+    conditions(predicate)
+        .iter()
+        .filter_map(|condition| match condition {
+            Condition::OracleAbove { oracle, key, .. } | Condition::OracleBelow { oracle, key, .. } => {
+                Some(RemoteCall {
+                    contract_id: oracle.clone(),
+                    method_name: "get_value".to_string(),
+                    args: serde_json::json!({ "key": key }).to_string().into_bytes(),
+                })
+            }
+            Condition::ViewEquals { contract_id, method_name, args, .. } => Some(RemoteCall {
+                contract_id: contract_id.clone(),
+                method_name: method_name.clone(),
+                args: args.clone().into_bytes(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Issues all remote reads as one joint promise.
+pub fn query(calls: Vec<RemoteCall>) -> Promise {
+    calls
+        .into_iter()
+        .map(|call| {
+            Promise::new(call.contract_id).function_call(
+                call.method_name.clone(),
+                call.args,
+                0,
+                GAS_FOR_PREDICATE_VIEW,
+            )
+        })
+        .reduce(|joint, promise| joint.and(promise))
+        .expect("No remote calls")
+}
+
+/// Evaluates the predicate. `results` holds the raw return values of the calls
+/// from `remote_calls`, in the same order, with `None` for failed calls.
+pub fn evaluate(predicate: &Predicate, maker_nonce: u64, results: &[Option<Vec<u8>>]) -> bool {
+    let mut results = results.iter();
+    let outcomes: Vec<bool> = conditions(predicate)
+        .iter()
+        .map(|condition| evaluate_condition(condition, maker_nonce, &mut results))
+        .collect();
+
+    match predicate {
+        Predicate::And { .. } => outcomes.iter().all(|outcome| *outcome),
+        Predicate::Or { .. } => outcomes.iter().any(|outcome| *outcome),
+    }
+}
+
+fn evaluate_condition<'a>(
+    condition: &Condition,
+    maker_nonce: u64,
+    results: &mut impl Iterator<Item = &'a Option<Vec<u8>>>,
+) -> bool {
+    match condition {
+        Condition::After { timestamp } => env::block_timestamp() >= *timestamp,
+        Condition::Before { timestamp } => env::block_timestamp() < *timestamp,
+        Condition::NonceEquals { nonce } => maker_nonce == *nonce,
+        Condition::OracleAbove { value, .. } => {
+            oracle_value(results.next()).is_some_and(|current| current >= value.0)
+        }
+        Condition::OracleBelow { value, .. } => {
+            oracle_value(results.next()).is_some_and(|current| current < value.0)
+        }
+        Condition::ViewEquals { expected, .. } => match results.next() {
+            Some(Some(result)) => {
+                let actual: Option<serde_json::Value> = serde_json::from_slice(result).ok();
+                let expected: Option<serde_json::Value> = serde_json::from_str(expected).ok();
+                actual.is_some() && actual == expected
+            }
+            _ => false,
+        },
+    }
+}
+
+fn oracle_value(result: Option<&Option<Vec<u8>>>) -> Option<u128> {
+    let bytes = result?.as_ref()?;
+    let value: Option<U128> = serde_json::from_slice(bytes).ok()?;
+    value.map(|value| value.0)
+}
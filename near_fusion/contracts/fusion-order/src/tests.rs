@@ -1,16 +1,51 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use super::*;
+    use crate::*;
+    use escrow::{AuctionPoint, Condition, IntegratorFee, Predicate};
     use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, VMContext};
+    use near_sdk::{testing_env, VMContext, Balance};
+
+    const NEAR: Balance = 1_000_000_000_000_000_000_000_000;
 
     fn get_context(predecessor_account_id: AccountId, block_timestamp: u64) -> VMContext {
         VMContextBuilder::new()
+            .current_account_id("contract.near".parse().unwrap())
             .predecessor_account_id(predecessor_account_id)
             .block_timestamp(block_timestamp)
+            .attached_deposit(NEAR / 10) // Covers order storage
             .build()
     }
 
+    fn deposit(contract: &mut FusionOrderContract, sender: AccountId, token: &str, amount: u128) {
+        let block_timestamp = env::block_timestamp();
+        testing_env!(get_context(token.parse().unwrap(), block_timestamp));
+        contract.storage_deposit(Some(sender.clone()));
+        contract.ft_on_transfer(sender.clone(), U128(amount), "".to_string());
+        testing_env!(get_context(sender, block_timestamp));
+    }
+
+    fn set_promise_results(results: Vec<PromiseResult>) {
+        testing_env!(
+            get_context("contract.near".parse().unwrap(), env::block_timestamp()),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            results
+        );
+    }
+
+    /// The receipt of the last fill, as emitted by the contract.
+    fn last_fill_receipt() -> FillReceipt {
+        let log = near_sdk::test_utils::get_logs()
+            .into_iter()
+            .rev()
+            .find(|log| log.contains("\"event\":\"order_filled\""))
+            .expect("No fill event");
+        let event: serde_json::Value = serde_json::from_str(log.trim_start_matches("EVENT_JSON:")).unwrap();
+        serde_json::from_value(event["data"][0].clone()).unwrap()
+    }
+
     fn create_test_order() -> FusionOrder {
         FusionOrder {
             order_hash: [1; 32],
@@ -22,6 +57,10 @@ mod tests {
             taking_amount: U128(2000),
             maker_traits: 0,
             salt: [2; 32],
+            integrator_fee: None,
+            exclusivity_end: None,
+            predicate: None,
+            expiry: None,
         }
     }
 
@@ -43,6 +82,7 @@ mod tests {
         testing_env!(context);
         
         let mut contract = FusionOrderContract::new(accounts(5), 30); // 0.3% fee
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
         let order = create_test_order();
         
         let order_hash = contract.create_order(order.clone(), None);
@@ -96,6 +136,7 @@ mod tests {
         testing_env!(context);
         
         let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
         let order = create_test_order();
         
         let order_hash = contract.create_order(order, None);
@@ -112,6 +153,7 @@ mod tests {
         testing_env!(context);
         
         let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
         let order = create_test_order();
         
         let order_hash = contract.create_order(order, None);
@@ -143,12 +185,1687 @@ mod tests {
         testing_env!(context);
         
         let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order = create_test_order();
+        
+        let order_hash = contract.create_order(order, None);
+        
+        let (remaining_making, remaining_taking) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 1000);
+        assert_eq!(remaining_taking.0, 2000);
+    }
+    #[test]
+    fn test_orders_by_maker_and_pair() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 2000);
+        let order = create_test_order();
+        let mut other_pair = create_test_order();
+        other_pair.taker_asset = "token3.near".parse().unwrap();
+        
+        let order_hash = contract.create_order(order, None);
+        let other_hash = contract.create_order(other_pair, None);
+        
+        let by_maker = contract.get_orders_by_maker(accounts(1), None, None);
+        assert_eq!(by_maker.len(), 2);
+        assert!(contract.get_orders_by_maker(accounts(2), None, None).is_empty());
+        
+        let by_pair = contract.get_active_orders_by_pair(
+            "token1.near".parse().unwrap(),
+            "token2.near".parse().unwrap(),
+            None,
+            None,
+        );
+        assert_eq!(by_pair.len(), 1);
+        assert_eq!(by_pair[0].order_hash, order_hash);
+        assert_eq!(by_pair[0].remaining_making_amount.0, 1000);
+        
+        // Cancelled orders drop out of the pair index but stay listed for the maker
+        contract.cancel_order(other_hash);
+        let by_pair = contract.get_active_orders_by_pair(
+            "token1.near".parse().unwrap(),
+            "token3.near".parse().unwrap(),
+            None,
+            None,
+        );
+        assert!(by_pair.is_empty());
+        assert_eq!(contract.get_orders_by_maker(accounts(1), None, None).len(), 2);
+    }
+
+    #[test]
+    fn test_open_orders_sorted_and_paginated() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            let mut contract = FusionOrderContract::new(accounts(5), 30);
+            deposit(&mut contract, accounts(1), "token1.near", 3000);
+            contract
+        };
+        
+        let mut hashes = vec![];
+        for i in 0..3u8 {
+            testing_env!(get_context(accounts(1), 100 * (3 - i as u64)));
+            let mut order = create_test_order();
+            order.salt = [i; 32];
+            hashes.push(contract.create_order(order, None));
+        }
+        
+        let open = contract.get_open_orders(None, None);
+        assert_eq!(contract.get_open_orders_count(), 3);
+        let open_hashes: Vec<[u8; 32]> = open.iter().map(|view| view.order_hash).collect();
+        assert_eq!(open_hashes, vec![hashes[2], hashes[1], hashes[0]]);
+        
+        let page = contract.get_open_orders(Some(1), Some(1));
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].order_hash, hashes[1]);
+    }
+
+    #[test]
+    fn test_order_view_includes_auction_price() {
+        let context = get_context(accounts(1), 1000);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        contract.create_order(create_test_order(), Some(create_test_auction()));
+        
+        let open = contract.get_open_orders(None, None);
+        assert_eq!(open[0].rate_bump, 100);
+        assert_eq!(open[0].current_taking_amount.0, 2020);
+    }
+
+    #[test]
+    fn test_order_view_flags_expired_orders() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let mut order = create_test_order();
+        order.expiry = Some(100);
+        contract.create_order(order, None);
+        assert!(!contract.get_open_orders(None, None)[0].is_expired);
+        
+        // Still listed until pruned, but marked so it is not offered for filling
+        testing_env!(get_context(accounts(1), 100));
+        let open = contract.get_open_orders(None, None);
+        assert_eq!(open.len(), 1);
+        assert!(open[0].is_expired);
+    }
+    #[test]
+    fn test_schedule_protocol_fee_after_notice() {
+        let context = get_context("contract.near".parse().unwrap(), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        contract.schedule_protocol_fee(50);
+        
+        let config = contract.get_protocol_fee_config();
+        assert_eq!(config.basis_points, 30);
+        assert_eq!(config.pending.unwrap().basis_points, 50);
+        
+        testing_env!(get_context("contract.near".parse().unwrap(), PROTOCOL_FEE_NOTICE_PERIOD));
+        let config = contract.get_protocol_fee_config();
+        assert_eq!(config.basis_points, 50);
+        assert!(config.pending.is_none());
+    }
+
+    #[test]
+    fn test_schedule_protocol_fee_receiver_after_notice() {
+        testing_env!(get_context("contract.near".parse().unwrap(), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        contract.schedule_protocol_fee(50);
+        contract.schedule_protocol_fee_receiver(accounts(4));
+        
+        // The fee change is kept and the receiver waits out the notice as well
+        let config = contract.get_protocol_fee_config();
+        assert_eq!(config.receiver, accounts(5));
+        let pending = config.pending.unwrap();
+        assert_eq!(pending.receiver, accounts(4));
+        assert_eq!(pending.basis_points, 50);
+        
+        testing_env!(get_context("contract.near".parse().unwrap(), PROTOCOL_FEE_NOTICE_PERIOD));
+        let config = contract.get_protocol_fee_config();
+        assert_eq!(config.receiver, accounts(4));
+        assert_eq!(config.basis_points, 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only fee receiver can claim")]
+    fn test_scheduled_receiver_cannot_claim_early() {
+        testing_env!(get_context("contract.near".parse().unwrap(), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        contract.schedule_protocol_fee_receiver(accounts(4));
+        
+        testing_env!(get_context(accounts(4), PROTOCOL_FEE_NOTICE_PERIOD - 1));
+        contract.claim_protocol_fees("token1.near".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid fee")]
+    fn test_schedule_protocol_fee_out_of_bounds() {
+        let context = get_context("contract.near".parse().unwrap(), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        contract.schedule_protocol_fee(MAX_PROTOCOL_FEE_BASIS_POINTS + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only fee receiver can claim")]
+    fn test_claim_protocol_fees_unauthorized() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        contract.claim_protocol_fees("token1.near".parse().unwrap());
+    }
+    #[test]
+    fn test_integrator_fee_covered_by_hash() {
         let order = create_test_order();
+        let mut with_fee = create_test_order();
+        with_fee.integrator_fee = Some(IntegratorFee {
+            receiver: accounts(4),
+            basis_points: 25,
+        });
+        assert_ne!(order.hash(), with_fee.hash());
+        
+        let mut other_bps = with_fee.clone();
+        other_bps.integrator_fee.as_mut().unwrap().basis_points = 30;
+        assert_ne!(with_fee.hash(), other_bps.hash());
+    }
+
+    #[test]
+    fn test_optional_hash_fields_are_tagged() {
+        // The same timestamp in a different optional field must not collide
+        let mut exclusive = create_test_order();
+        exclusive.exclusivity_end = Some(5000);
+        let mut expiring = create_test_order();
+        expiring.expiry = Some(5000);
+        assert_ne!(exclusive.hash(), expiring.hash());
+    }
+
+    #[test]
+    #[should_panic(expected = "Integrator fee too high")]
+    fn test_integrator_fee_limit() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        let mut order = create_test_order();
+        order.integrator_fee = Some(IntegratorFee {
+            receiver: accounts(4),
+            basis_points: MAX_INTEGRATOR_FEE_BASIS_POINTS + 1,
+        });
+        contract.create_order(order, None);
+    }
+    #[test]
+    #[should_panic(expected = "Insufficient balance")]
+    fn test_create_order_requires_deposit() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 999);
+        contract.create_order(create_test_order(), None);
+    }
+
+    #[test]
+    fn test_fill_via_transfer_call_settles_internally() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        assert_eq!(contract.get_balance(accounts(1), "token1.near".parse().unwrap()).0, 0);
+        
+        // Taker sends more than needed; the excess is reported as unused
+        testing_env!(get_context("token2.near".parse().unwrap(), 0));
+        let msg = serde_json::json!({
+            "action": "fill",
+            "order_hash": order_hash,
+            "making_amount": U128(500),
+            "taking_amount": U128(1000),
+            "resolver_fee": null,
+        }).to_string();
+        contract.ft_on_transfer(accounts(2), U128(1200), msg);
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 0);
+        
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 500);
+        
+        let receipt = last_fill_receipt();
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        assert_eq!(contract.on_fill_settled(receipt, U128(200), Some(U128(1200))).0, 200);
+        // 0.3% of the 500 making amount
+        assert_eq!(contract.get_accrued_protocol_fees("token1.near".parse().unwrap()).0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient balance")]
+    fn test_fill_order_requires_taker_balance() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 999);
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+    }
+
+    #[test]
+    fn test_cancel_releases_reserved_balance() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 1000);
+        contract.fill_order(order_hash, U128(250), U128(500), None, None);
+        
+        testing_env!(get_context(accounts(1), 7));
+        let settlement = contract.cancel_order(order_hash);
+        assert_eq!(contract.get_balance(accounts(1), "token1.near".parse().unwrap()).0, 750);
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 500);
+        
+        assert_eq!(settlement.reason, SettlementReason::Cancelled);
+        assert_eq!(settlement.filled_making_amount.0, 250);
+        assert_eq!(settlement.filled_taking_amount.0, 500);
+        assert_eq!(settlement.released_making_amount.0, 750);
+        assert_eq!(settlement.settled_at, 7);
+        assert!(contract.get_order(order_hash).unwrap().settlement.is_some());
+    }
+
+    #[test]
+    fn test_cancel_pays_for_reopened_balance() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        let storage_balance = contract.get_storage_balance(accounts(1)).0;
         
+        // Reserving the full deposit closed the maker's balance; cancelling reopens it
+        contract.cancel_order(order_hash);
+        assert_eq!(contract.get_balance(accounts(1), "token1.near".parse().unwrap()).0, 1000);
+        assert!(contract.get_storage_balance(accounts(1)).0 < storage_balance);
+    }
+
+    #[test]
+    fn test_fill_entries_opened_with_order() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let mut order = create_test_order();
+        order.integrator_fee = Some(IntegratorFee {
+            receiver: accounts(4),
+            basis_points: 50,
+        });
         let order_hash = contract.create_order(order, None);
         
+        let token1: AccountId = "token1.near".parse().unwrap();
+        let token2: AccountId = "token2.near".parse().unwrap();
+        assert!(contract.pair_stats.get(&(token1.clone(), token2.clone())).is_some());
+        assert_eq!(contract.protocol_fees.get(&token1), Some(0));
+        assert_eq!(contract.protocol_fees.get(&token2), Some(0));
+        assert_eq!(contract.integrator_fees.get(&(accounts(4), token1.clone())), Some(0));
+        
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(1000), U128(2000), None, None);
+        let receipt = last_fill_receipt();
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        assert_eq!(contract.get_pair_stats(token1.clone(), token2).fill_count, 1);
+        
+        // Claiming leaves the entry in place for the next fill
+        testing_env!(get_context(accounts(5), 0));
+        contract.claim_protocol_fees(token1.clone());
+        assert_eq!(contract.protocol_fees.get(&token1), Some(0));
+    }
+
+    #[test]
+    fn test_cancel_summary_updated_when_fill_reverts() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 1000);
+        contract.fill_order(order_hash, U128(250), U128(500), None, None);
+        let receipt = last_fill_receipt();
+        
+        testing_env!(get_context(accounts(1), 0));
+        contract.cancel_order(order_hash);
+        
+        set_promise_results(vec![PromiseResult::Failed]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        
+        let settlement = contract.get_order(order_hash).unwrap().settlement.unwrap();
+        assert_eq!(settlement.filled_making_amount.0, 0);
+        assert_eq!(settlement.released_making_amount.0, 1000);
+        assert_eq!(contract.get_balance(accounts(1), "token1.near".parse().unwrap()).0, 1000);
+    }
+
+    #[test]
+    fn test_full_fill_records_settlement() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(1000), U128(2000), None, None);
+        let receipt = last_fill_receipt();
+        assert!(contract.get_order(order_hash).unwrap().settlement.is_none());
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        
+        let settlement = contract.get_order(order_hash).unwrap().settlement.unwrap();
+        assert_eq!(settlement.reason, SettlementReason::Filled);
+        assert_eq!(settlement.filled_making_amount.0, 1000);
+        assert_eq!(settlement.released_making_amount.0, 0);
+    }
+
+    #[test]
+    fn test_failed_fill_payout_is_rolled_back() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(1000), U128(2000), None, None);
+        assert_eq!(contract.get_open_orders_count(), 0);
+        let receipt = last_fill_receipt();
+        
+        set_promise_results(vec![PromiseResult::Failed]);
+        let refunded = contract.on_fill_settled(receipt, U128(0), None);
+        assert_eq!(refunded.0, 2000);
+        
         let (remaining_making, remaining_taking) = contract.get_remaining_amounts(order_hash);
         assert_eq!(remaining_making.0, 1000);
         assert_eq!(remaining_taking.0, 2000);
+        assert_eq!(contract.get_open_orders_count(), 1);
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 2000);
+        assert_eq!(contract.get_accrued_protocol_fees("token1.near".parse().unwrap()).0, 0);
+    }
+
+    #[test]
+    fn test_failed_transfer_call_fill_refunds_through_token() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        testing_env!(get_context("token2.near".parse().unwrap(), 0));
+        let msg = serde_json::json!({
+            "action": "fill",
+            "order_hash": order_hash,
+            "making_amount": U128(500),
+            "taking_amount": U128(1000),
+        }).to_string();
+        contract.ft_on_transfer(accounts(2), U128(1200), msg);
+        let receipt = last_fill_receipt();
+        
+        set_promise_results(vec![PromiseResult::Failed]);
+        assert_eq!(contract.on_fill_settled(receipt.clone(), U128(200), Some(U128(1200))).0, 1200);
+        // Nothing is credited internally, the token contract returns the full amount
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 0);
+        
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 1000);
+    }
+
+    #[test]
+    fn test_failed_transfer_call_top_up_returns_deposit_part() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        deposit(&mut contract, accounts(2), "token2.near", 400);
+        
+        // The transfer covers 600 of the 1000 taking amount, the deposit the rest
+        testing_env!(get_context("token2.near".parse().unwrap(), 0));
+        let msg = serde_json::json!({
+            "action": "fill",
+            "order_hash": order_hash,
+            "making_amount": U128(500),
+            "taking_amount": U128(1000),
+        }).to_string();
+        contract.ft_on_transfer(accounts(2), U128(600), msg);
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 0);
+        let receipt = last_fill_receipt();
+        
+        set_promise_results(vec![PromiseResult::Failed]);
+        assert_eq!(contract.on_fill_settled(receipt, U128(0), Some(U128(600))).0, 600);
+        // The token contract refunds the transfer, the top-up returns to the deposit
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 400);
+        
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 1000);
+    }
+
+    #[test]
+    fn test_successful_fill_payout_keeps_fill() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 1000);
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+        let receipt = last_fill_receipt();
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        assert_eq!(contract.on_fill_settled(receipt, U128(0), None).0, 0);
+        
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 500);
+    }
+    #[test]
+    fn test_fees_accrue_only_once_fill_settles() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(1000), U128(2000), None, None);
+        let receipt = last_fill_receipt();
+        assert_eq!(receipt.protocol_fee.0, 3);
+        
+        // The fee of a fill whose payout is still in flight cannot be claimed
+        assert_eq!(contract.get_accrued_protocol_fees("token1.near".parse().unwrap()).0, 0);
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        assert_eq!(contract.get_accrued_protocol_fees("token1.near".parse().unwrap()).0, 3);
+    }
+
+    #[test]
+    fn test_mul_div_rounding() {
+        assert_eq!(mul_div(10, 1, 3, false), 3);
+        assert_eq!(mul_div(10, 1, 3, true), 4);
+        assert_eq!(mul_div(9, 1, 3, true), 3);
+        // Intermediate product does not fit in u128
+        let big = 10u128.pow(30);
+        assert_eq!(mul_div(big, big, big, false), big);
+        assert_eq!(mul_div(u128::MAX, 3, 4, false), u128::MAX / 4 * 3 + 2);
+    }
+
+    #[test]
+    fn test_fill_by_making_rounds_in_maker_favour() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 3);
+        let mut order = create_test_order();
+        order.making_amount = U128(3);
+        order.taking_amount = U128(10);
+        let order_hash = contract.create_order(order, None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 100);
+        let mut paid = vec![];
+        for _ in 0..3 {
+            let before = contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0;
+            contract.fill_order_by_making(order_hash, U128(1), None, None);
+            paid.push(before - contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0);
+        }
+        
+        // Splitting the order never costs the maker anything: 4 + 3 + 3 == 10
+        assert_eq!(paid, vec![4, 3, 3]);
+        assert_eq!(contract.get_remaining_amounts(order_hash), (U128(0), U128(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Fill amount too small")]
+    fn test_fill_by_taking_rejects_dust() {
+        let context = get_context(accounts(1), 0);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 3);
+        let mut order = create_test_order();
+        order.making_amount = U128(3);
+        order.taking_amount = U128(10);
+        let order_hash = contract.create_order(order, None);
+        
+        // 3 taking units are worth 0.9 making units, which rounds down to nothing
+        deposit(&mut contract, accounts(2), "token2.near", 100);
+        contract.fill_order_by_taking(order_hash, U128(3), None, None);
+    }
+
+    #[test]
+    fn test_fill_by_taking_applies_auction_bump() {
+        let context = get_context(accounts(1), 1000);
+        testing_env!(context);
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), Some(create_test_auction()));
+        
+        deposit(&mut contract, accounts(2), "token2.near", 1000);
+        contract.fill_order_by_taking(order_hash, U128(333), None, None);
+        
+        // 333 * 1000 / 2000 = 166.5 -> 166 making; 1% bump on 333 rounds up to 4
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 1000 - 166);
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 1000 - 337);
+    }
+
+    #[test]
+    #[should_panic(expected = "Taking amount exceeds limit")]
+    fn test_fill_limit_rejects_auction_bump() {
+        testing_env!(get_context(accounts(1), 0));
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), Some(create_test_auction()));
+        
+        // The 1% bump makes the fill cost 1010
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        let limits = FillLimits { max_taking_amount: Some(U128(1000)), ..Default::default() };
+        contract.fill_order(order_hash, U128(500), U128(1000), None, Some(limits));
+    }
+
+    #[test]
+    #[should_panic(expected = "Making amount below limit")]
+    fn test_fill_limit_rejects_protocol_fee() {
+        testing_env!(get_context(accounts(1), 0));
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 1000);
+        let limits = FillLimits { min_making_amount: Some(U128(500)), ..Default::default() };
+        contract.fill_order(order_hash, U128(500), U128(1000), None, Some(limits));
+    }
+
+    #[test]
+    #[should_panic(expected = "Fill deadline passed")]
+    fn test_fill_limit_rejects_after_deadline() {
+        testing_env!(get_context(accounts(1), 0));
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        testing_env!(get_context(accounts(2), 2000));
+        deposit(&mut contract, accounts(2), "token2.near", 1000);
+        let limits = FillLimits { deadline: Some(1000), ..Default::default() };
+        contract.fill_order(order_hash, U128(500), U128(1000), None, Some(limits));
+    }
+
+    #[test]
+    fn test_fill_within_limits_via_transfer_call() {
+        testing_env!(get_context(accounts(1), 0));
+        
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), Some(create_test_auction()));
+        
+        testing_env!(get_context("token2.near".parse().unwrap(), 0));
+        let msg = serde_json::json!({
+            "action": "fill",
+            "order_hash": order_hash,
+            "making_amount": U128(500),
+            "taking_amount": U128(1000),
+            "resolver_fee": null,
+            "limits": {
+                "max_taking_amount": U128(1010),
+                "min_making_amount": U128(498),
+                "deadline": 0,
+            },
+        }).to_string();
+        contract.ft_on_transfer(accounts(2), U128(1010), msg);
+        
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 500);
+    }
+
+    #[test]
+    fn test_transfer_fill_tops_up_existing_deposit() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        deposit(&mut contract, accounts(2), "token2.near", 400);
+        
+        // The transfer alone does not cover the fill, the deposit makes up the rest
+        testing_env!(get_context("token2.near".parse().unwrap(), 0));
+        let msg = serde_json::json!({
+            "action": "fill",
+            "order_hash": order_hash,
+            "making_amount": U128(500),
+            "taking_amount": U128(1000),
+        }).to_string();
+        contract.ft_on_transfer(accounts(2), U128(600), msg);
+        
+        assert_eq!(last_fill_receipt().actual_taking_amount.0, 1000);
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 0);
+    }
+
+    #[test]
+    fn test_surplus_shared_between_maker_and_protocol() {
+        testing_env!(get_context("contract.near".parse().unwrap(), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        contract.set_surplus_shares(5_000, 2_000);
+        
+        testing_env!(get_context(accounts(1), 0));
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), Some(create_test_auction()));
+        
+        // The 1% bump on 1000 is a surplus of 10: 5 to the maker, 2 to the
+        // protocol and 3 never charged to the taker
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+        
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 2000 - 1007);
+        let receipt = last_fill_receipt();
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        assert_eq!(contract.get_accrued_protocol_fees("token2.near".parse().unwrap()).0, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid surplus shares")]
+    fn test_surplus_shares_bounded() {
+        testing_env!(get_context("contract.near".parse().unwrap(), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        contract.set_surplus_shares(8_000, 3_000);
+    }
+
+    #[test]
+    fn test_amend_order_shrinks_and_tightens() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(200), U128(400), None, None);
+        let receipt = last_fill_receipt();
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        
+        // 800 left at 1:2; keep 500 of it at 1:3
+        testing_env!(get_context(accounts(1), 10));
+        contract.amend_order(order_hash, Some(U128(500)), Some(U128(1500)), None, None);
+        
+        assert_eq!(contract.get_remaining_amounts(order_hash), (U128(500), U128(1500)));
+        assert_eq!(contract.get_balance(accounts(1), "token1.near".parse().unwrap()).0, 300);
+        
+        let amendments = contract.get_order_amendments(order_hash);
+        assert_eq!(amendments.len(), 1);
+        assert_eq!(amendments[0].previous_making_amount.0, 800);
+        assert_eq!(amendments[0].amended_at, 10);
+        
+        // The signed order and the fill totals are left untouched
+        let order_state = contract.get_order(order_hash).unwrap();
+        assert_eq!(order_state.order.making_amount.0, 1000);
+        assert_eq!((order_state.filled_making_amount, order_state.filled_taking_amount), (200, 400));
+        assert_eq!(order_state.order.hash(), order_hash);
+        
+        // The next fill is priced on the amended terms only
+        testing_env!(get_context(accounts(2), 10));
+        contract.fill_order_by_making(order_hash, U128(100), None, None);
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 2000 - 400 - 300);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only tighten price")]
+    fn test_amend_order_cannot_loosen_price() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        contract.amend_order(order_hash, None, Some(U128(1999)), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fill in flight")]
+    fn test_amend_order_waits_for_settling_fill() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(200), U128(400), None, None);
+        
+        testing_env!(get_context(accounts(1), 0));
+        contract.amend_order(order_hash, Some(U128(500)), None, None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nothing to amend")]
+    fn test_amend_order_rejects_no_op() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        contract.amend_order(order_hash, Some(U128(1000)), Some(U128(2000)), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Too many amendments")]
+    fn test_amend_order_history_is_capped() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        for making_amount in (990..1000).rev() {
+            contract.amend_order(order_hash, Some(U128(making_amount)), None, None, None);
+        }
+    }
+
+    #[test]
+    fn test_amend_order_charges_storage() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        let storage_deposit = contract.get_order(order_hash).unwrap().storage_deposit;
+        
+        contract.amend_order(order_hash, Some(U128(900)), None, None, None);
+        assert!(contract.get_order(order_hash).unwrap().storage_deposit > storage_deposit);
+    }
+
+    #[test]
+    fn test_amend_order_extends_expiry_and_auction() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let mut order = create_test_order();
+        order.expiry = Some(5000);
+        let order_hash = contract.create_order(order, Some(create_test_auction()));
+        
+        contract.amend_order(order_hash, None, None, Some(7200), Some(9000));
+        
+        let order_state = contract.get_order(order_hash).unwrap();
+        assert_eq!(order_state.order.expiry, Some(5000));
+        assert_eq!(order_state.amended_terms.unwrap().expiry, Some(9000));
+        assert_eq!(order_state.auction.unwrap().duration, 7200);
+        
+        // Filling past the original expiry now works
+        testing_env!(get_context(accounts(2), 6000));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Order expired")]
+    fn test_fill_after_expiry() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let mut order = create_test_order();
+        order.expiry = Some(5000);
+        let order_hash = contract.create_order(order, None);
+        
+        testing_env!(get_context(accounts(2), 5000));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+    }
+
+    #[test]
+    fn test_settled_fills_recorded_in_history_and_stats() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), Some(create_test_auction()));
+        
+        testing_env!(get_context(accounts(2), 1900));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(400), U128(800), None, None);
+        let receipt = last_fill_receipt();
+        
+        // Nothing is recorded until the payout settles
+        assert!(contract.get_fill_history(order_hash).is_empty());
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        
+        let history = contract.get_fill_history(order_hash);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].taker, accounts(2));
+        assert_eq!(history[0].rate_bump, 75);
+        assert_eq!(history[0].filled_at, 1900);
+        
+        let stats = contract.get_pair_stats("token1.near".parse().unwrap(), "token2.near".parse().unwrap());
+        assert_eq!(stats.fill_count, 1);
+        assert_eq!(stats.making_volume.0, 400);
+        assert_eq!(stats.taking_volume.0, 806);
+    }
+
+    #[test]
+    fn test_failed_fill_not_recorded() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 30);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+        let receipt = last_fill_receipt();
+        
+        set_promise_results(vec![PromiseResult::Failed]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        
+        assert!(contract.get_fill_history(order_hash).is_empty());
+        let stats = contract.get_pair_stats("token1.near".parse().unwrap(), "token2.near".parse().unwrap());
+        assert_eq!(stats.fill_count, 0);
+    }
+
+    #[test]
+    fn test_create_order_charges_storage() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        let storage_deposit = contract.get_order(order_hash).unwrap().storage_deposit;
+        assert!(storage_deposit > 0 && storage_deposit < NEAR / 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage deposit")]
+    fn test_create_order_without_storage_deposit() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("contract.near".parse().unwrap())
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.create_order(create_test_order(), None);
+    }
+
+    #[test]
+    fn test_prune_cancelled_order_tips_caller() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        let storage_deposit = contract.get_order(order_hash).unwrap().storage_deposit;
+        contract.cancel_order(order_hash);
+        
+        testing_env!(get_context(accounts(4), 0));
+        let tip = contract.prune_order(order_hash);
+        
+        assert_eq!(tip.0, storage_deposit / 10);
+        assert!(contract.get_order(order_hash).is_none());
+        assert!(contract.get_orders_by_maker(accounts(1), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_prune_expired_order_releases_reservation() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let mut order = create_test_order();
+        order.expiry = Some(5000);
+        let order_hash = contract.create_order(order, None);
+        
+        testing_env!(get_context(accounts(4), 5000));
+        contract.prune_order(order_hash);
+        
+        assert_eq!(contract.get_balance(accounts(1), "token1.near".parse().unwrap()).0, 1000);
+        assert_eq!(contract.get_open_orders_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage balance")]
+    fn test_deposit_requires_storage_balance() {
+        testing_env!(get_context("token1.near".parse().unwrap(), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        contract.ft_on_transfer(accounts(1), U128(1000), "".to_string());
+    }
+
+    #[test]
+    fn test_deposit_charges_storage_balance() {
+        testing_env!(get_context("token1.near".parse().unwrap(), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        contract.storage_deposit(Some(accounts(1)));
+        contract.ft_on_transfer(accounts(1), U128(1000), "".to_string());
+        let storage_balance = contract.get_storage_balance(accounts(1)).0;
+        assert!(storage_balance < NEAR / 10);
+        
+        // Topping up an existing balance uses no new storage
+        contract.ft_on_transfer(accounts(1), U128(1000), "".to_string());
+        assert_eq!(contract.get_storage_balance(accounts(1)).0, storage_balance);
+    }
+
+    #[test]
+    #[should_panic(expected = "Order still active")]
+    fn test_prune_active_order() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        
+        testing_env!(get_context(accounts(4), 0));
+        contract.prune_order(order_hash);
+    }
+
+    fn create_twap_order(contract: &mut FusionOrderContract) -> [u8; 32] {
+        testing_env!(get_context(accounts(1), 0));
+        deposit(contract, accounts(1), "token1.near", 1000);
+        let auction = AuctionDetails {
+            start_time: 1000,
+            duration: 500,
+            initial_rate_bump: 100,
+            points: vec![],
+        };
+        let twap = TwapSchedule { start_time: 1000, slice_count: 4, interval: 1000 };
+        contract.create_twap_order(create_test_order(), Some(auction), twap)
+    }
+
+    #[test]
+    fn test_twap_fills_capped_to_unlocked_slices() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = create_twap_order(&mut contract);
+        
+        // Second slice unlocked, its auction just started
+        testing_env!(get_context(accounts(2), 2000));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order_by_making(order_hash, U128(1000), None, None);
+        
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 500);
+        // 1000 for 500 making plus the fresh slice's 1% bump
+        assert_eq!(contract.get_balance(accounts(2), "token2.near".parse().unwrap()).0, 2000 - 1010);
+    }
+
+    #[test]
+    fn test_twap_fill_by_taking_capped_to_unlocked_slices() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = create_twap_order(&mut contract);
+        
+        testing_env!(get_context(accounts(2), 2000));
+        deposit(&mut contract, accounts(2), "token2.near", 3000);
+        contract.fill_order_by_taking(order_hash, U128(2000), None, None);
+        
+        let receipt = last_fill_receipt();
+        assert_eq!(receipt.making_amount.0, 500);
+        assert_eq!(receipt.taking_amount.0, 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Exceeds fillable amount")]
+    fn test_twap_rejects_fill_beyond_unlocked() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = create_twap_order(&mut contract);
+        
+        testing_env!(get_context(accounts(2), 1500));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+    }
+
+    #[test]
+    fn test_twap_schedule_view() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = create_twap_order(&mut contract);
+        
+        testing_env!(get_context(accounts(2), 2500));
+        let schedule = contract.get_twap_schedule(order_hash);
+        assert_eq!(schedule.len(), 4);
+        assert_eq!(schedule[3].unlock_time, 4000);
+        assert_eq!(schedule[3].making_amount.0, 250);
+        assert_eq!(schedule[2].auction.as_ref().unwrap().start_time, 3000);
+        assert!(schedule[1].is_unlocked && !schedule[2].is_unlocked);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid TWAP schedule")]
+    fn test_twap_requires_multiple_slices() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let twap = TwapSchedule { start_time: 0, slice_count: 1, interval: 1000 };
+        contract.create_twap_order(create_test_order(), None, twap);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid TWAP schedule")]
+    fn test_twap_rejects_overflowing_schedule() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let twap = TwapSchedule { start_time: 0, slice_count: 4, interval: u64::MAX / 2 };
+        contract.create_twap_order(create_test_order(), None, twap);
+    }
+
+    fn create_recurring_order(contract: &mut FusionOrderContract) -> [u8; 32] {
+        testing_env!(get_context(accounts(1), 0));
+        deposit(contract, accounts(1), "token1.near", 1000);
+        let recurring = RecurringSchedule {
+            start_time: 1000,
+            period: 1000,
+            amount_per_period: U128(300),
+        };
+        contract.create_recurring_order(create_test_order(), None, recurring)
+    }
+
+    #[test]
+    fn test_recurring_order_budget_resets_each_period() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = create_recurring_order(&mut contract);
+        
+        testing_env!(get_context(accounts(2), 1200));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order_by_making(order_hash, U128(1000), None, None);
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 700);
+        assert_eq!(contract.get_fillable_amount(order_hash).0, 0);
+        
+        // Unspent budget does not carry over, a new period opens a fresh one
+        testing_env!(get_context(accounts(2), 3500));
+        let period = contract.get_recurring_period(order_hash);
+        assert_eq!(period.period_index, 2);
+        assert_eq!(period.period_start, 3000);
+        assert_eq!(period.available_making_amount.0, 300);
+        
+        contract.fill_order_by_making(order_hash, U128(1000), None, None);
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 400);
+    }
+
+    #[test]
+    #[should_panic(expected = "Exceeds fillable amount")]
+    fn test_recurring_order_rejects_fill_over_budget() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = create_recurring_order(&mut contract);
+        
+        testing_env!(get_context(accounts(2), 1200));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(400), U128(800), None, None);
+    }
+
+    #[test]
+    fn test_recurring_fill_by_taking_capped_to_budget() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = create_recurring_order(&mut contract);
+        
+        testing_env!(get_context(accounts(2), 1200));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order_by_taking(order_hash, U128(2000), None, None);
+        
+        let receipt = last_fill_receipt();
+        assert_eq!(receipt.making_amount.0, 300);
+        assert_eq!(receipt.taking_amount.0, 600);
+    }
+
+    #[test]
+    fn test_recurring_period_start_saturates() {
+        let recurring = RecurringSchedule {
+            start_time: 1000,
+            period: u64::MAX / 2,
+            amount_per_period: U128(300),
+        };
+        assert_eq!(recurring.period_start(1), 1000 + u64::MAX / 2);
+        assert_eq!(recurring.period_start(2), u64::MAX);
+        assert_eq!(recurring.period_auction(&create_test_auction(), 3).start_time, u64::MAX);
+    }
+
+    #[test]
+    fn test_recurring_budget_restored_when_fill_reverts() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = create_recurring_order(&mut contract);
+        
+        testing_env!(get_context(accounts(2), 1200));
+        deposit(&mut contract, accounts(2), "token2.near", 2000);
+        contract.fill_order(order_hash, U128(300), U128(600), None, None);
+        let receipt = last_fill_receipt();
+        
+        set_promise_results(vec![PromiseResult::Failed]);
+        contract.on_fill_settled(receipt, U128(0), None);
+        assert_eq!(contract.get_fillable_amount(order_hash).0, 300);
+    }
+
+    fn create_crossing_orders(contract: &mut FusionOrderContract, making_amount_b: u128) -> ([u8; 32], [u8; 32]) {
+        testing_env!(get_context(accounts(1), 0));
+        deposit(contract, accounts(1), "token1.near", 1000);
+        let order_hash_a = contract.create_order(create_test_order(), None);
+        
+        testing_env!(get_context(accounts(3), 0));
+        deposit(contract, accounts(3), "token2.near", making_amount_b);
+        let mut order_b = create_test_order();
+        order_b.maker = accounts(3);
+        order_b.maker_asset = "token2.near".parse().unwrap();
+        order_b.taker_asset = "token1.near".parse().unwrap();
+        order_b.making_amount = U128(making_amount_b);
+        order_b.taking_amount = U128(1000);
+        let order_hash_b = contract.create_order(order_b, None);
+        (order_hash_a, order_hash_b)
+    }
+
+    #[test]
+    fn test_match_orders_pays_spread_to_matcher() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let (order_hash_a, order_hash_b) = create_crossing_orders(&mut contract, 2200);
+        
+        testing_env!(get_context(accounts(4), 0));
+        contract.storage_deposit(None);
+        let result = contract.match_orders(order_hash_a, order_hash_b, MatchAmounts {
+            making_amount_a: U128(1000),
+            making_amount_b: U128(2200),
+        });
+        
+        assert_eq!(result.spread_a.0, 0);
+        assert_eq!(result.spread_b.0, 200);
+        assert_eq!(contract.get_balance(accounts(4), "token2.near".parse().unwrap()).0, 200);
+        assert_eq!(contract.get_balance(accounts(4), "token1.near".parse().unwrap()).0, 0);
+        assert_eq!(contract.get_balance(accounts(1), "token2.near".parse().unwrap()).0, 2000);
+        assert_eq!(contract.get_balance(accounts(3), "token1.near".parse().unwrap()).0, 1000);
+        
+        // Both fills are final right away
+        assert_eq!(contract.get_fill_history(order_hash_a).len(), 1);
+        assert!(contract.get_order(order_hash_b).unwrap().settlement.is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient storage balance")]
+    fn test_match_orders_requires_matcher_storage() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let (order_hash_a, order_hash_b) = create_crossing_orders(&mut contract, 2200);
+        
+        // The spread opens a balance for the matcher, who has prepaid nothing
+        testing_env!(get_context(accounts(4), 0));
+        contract.match_orders(order_hash_a, order_hash_b, MatchAmounts {
+            making_amount_a: U128(1000),
+            making_amount_b: U128(2200),
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Prices do not cross")]
+    fn test_match_orders_requires_crossing_prices() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let (order_hash_a, order_hash_b) = create_crossing_orders(&mut contract, 1900);
+        
+        testing_env!(get_context(accounts(4), 0));
+        contract.match_orders(order_hash_a, order_hash_b, MatchAmounts {
+            making_amount_a: U128(1000),
+            making_amount_b: U128(1900),
+        });
+    }
+
+    fn open_bidding_round(contract: &mut FusionOrderContract) -> [u8; 32] {
+        testing_env!(get_context("contract.near".parse().unwrap(), 0));
+        contract.add_resolver(accounts(3));
+        contract.add_resolver(accounts(4));
+        
+        testing_env!(get_context(accounts(1), 0));
+        deposit(contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), None);
+        contract.open_bidding(order_hash, BiddingSchedule {
+            commit_end: 100,
+            reveal_end: 200,
+            fill_window: 100,
+            bond: U128(NEAR / 10),
+        });
+        
+        for (resolver, rate_bump) in [(accounts(3), 50), (accounts(4), 80)] {
+            testing_env!(get_context(resolver.clone(), 10));
+            let commitment = contract.get_bid_commitment(resolver, rate_bump, "salt".to_string());
+            contract.commit_bid(order_hash, commitment);
+        }
+        order_hash
+    }
+
+    #[test]
+    fn test_best_revealed_bid_fills_at_its_price() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = open_bidding_round(&mut contract);
+        
+        for (resolver, rate_bump) in [(accounts(3), 50), (accounts(4), 80)] {
+            testing_env!(get_context(resolver, 150));
+            contract.reveal_bid(order_hash, rate_bump, "salt".to_string());
+        }
+        
+        testing_env!(get_context(accounts(4), 200));
+        deposit(&mut contract, accounts(4), "token2.near", 3000);
+        contract.fill_order(order_hash, U128(1000), U128(2000), None, None);
+        // 0.8% bump from the winning bid
+        assert_eq!(contract.get_balance(accounts(4), "token2.near".parse().unwrap()).0, 3000 - 2016);
+        
+        assert_eq!(contract.settle_bidding(order_hash).0, 0);
+        assert!(contract.get_bidding_round(order_hash).unwrap().is_settled);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bidding in progress")]
+    fn test_no_fills_while_bidding() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = open_bidding_round(&mut contract);
+        
+        testing_env!(get_context(accounts(2), 150));
+        deposit(&mut contract, accounts(2), "token2.near", 3000);
+        contract.fill_order(order_hash, U128(1000), U128(2000), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Exclusive bid window")]
+    fn test_losing_bidder_blocked_in_winner_window() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = open_bidding_round(&mut contract);
+        
+        testing_env!(get_context(accounts(4), 150));
+        contract.reveal_bid(order_hash, 80, "salt".to_string());
+        
+        testing_env!(get_context(accounts(3), 250));
+        deposit(&mut contract, accounts(3), "token2.near", 3000);
+        contract.fill_order(order_hash, U128(1000), U128(2000), None, None);
+    }
+
+    #[test]
+    fn test_unrevealed_and_idle_winner_forfeit_bonds() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = open_bidding_round(&mut contract);
+        
+        testing_env!(get_context(accounts(4), 150));
+        contract.reveal_bid(order_hash, 80, "salt".to_string());
+        
+        // The winner never fills and accounts(3) never revealed
+        testing_env!(get_context(accounts(2), 300));
+        assert_eq!(contract.settle_bidding(order_hash).0, 2 * NEAR / 10);
+        
+        // The order is open to everyone again
+        deposit(&mut contract, accounts(2), "token2.near", 3000);
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+    }
+
+    #[test]
+    fn test_prune_removes_bidding_round() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = open_bidding_round(&mut contract);
+        // Room for the fill history and the round's bids is paid up front
+        let reserved = MAX_FILL_HISTORY as u64 * FILL_RECORD_STORAGE + bidding::MAX_BIDS as u64 * SEALED_BID_STORAGE;
+        let storage_deposit = contract.get_order(order_hash).unwrap().storage_deposit;
+        assert!(storage_deposit > reserved as u128 * env::storage_byte_cost());
+        
+        testing_env!(get_context(accounts(1), 50));
+        contract.cancel_order(order_hash);
+        contract.settle_bidding(order_hash);
+        contract.prune_order(order_hash);
+        
+        assert!(contract.get_order(order_hash).is_none());
+        assert!(contract.get_bidding_round(order_hash).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Commitment mismatch")]
+    fn test_reveal_must_match_commitment() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = open_bidding_round(&mut contract);
+        
+        testing_env!(get_context(accounts(3), 150));
+        contract.reveal_bid(order_hash, 90, "salt".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Rate bump too high")]
+    fn test_reveal_rejects_rate_bump_over_basis_points() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = open_bidding_round(&mut contract);
+        
+        testing_env!(get_context("contract.near".parse().unwrap(), 10));
+        contract.add_resolver(accounts(0));
+        testing_env!(get_context(accounts(0), 10));
+        let commitment = contract.get_bid_commitment(accounts(0), BASIS_POINTS + 1, "salt".to_string());
+        contract.commit_bid(order_hash, commitment);
+        
+        testing_env!(get_context(accounts(0), 150));
+        contract.reveal_bid(order_hash, BASIS_POINTS + 1, "salt".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Rate bump below auction")]
+    fn test_reveal_rejects_rate_bump_below_auction() {
+        testing_env!(get_context("contract.near".parse().unwrap(), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        contract.add_resolver(accounts(3));
+        
+        testing_env!(get_context(accounts(1), 0));
+        deposit(&mut contract, accounts(1), "token1.near", 1000);
+        let order_hash = contract.create_order(create_test_order(), Some(create_test_auction()));
+        contract.open_bidding(order_hash, BiddingSchedule {
+            commit_end: 100,
+            reveal_end: 200,
+            fill_window: 100,
+            bond: U128(NEAR / 10),
+        });
+        
+        // The auction still pays its full 1% bump when the winner takes over
+        testing_env!(get_context(accounts(3), 10));
+        let commitment = contract.get_bid_commitment(accounts(3), 50, "salt".to_string());
+        contract.commit_bid(order_hash, commitment);
+        testing_env!(get_context(accounts(3), 150));
+        contract.reveal_bid(order_hash, 50, "salt".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Bidding settled")]
+    fn test_reveal_rejected_after_early_settle() {
+        testing_env!(get_context(accounts(1), 0));
+        let mut contract = FusionOrderContract::new(accounts(5), 0);
+        let order_hash = open_bidding_round(&mut contract);
+        
+        // Cancelling lets the round settle before the reveal phase ends
+        testing_env!(get_context(accounts(1), 120));
+        contract.cancel_order(order_hash);
+        contract.settle_bidding(order_hash);
+        
+        testing_env!(get_context(accounts(3), 150));
+        contract.reveal_bid(order_hash, 50, "salt".to_string());
+    }
+
+    fn create_exclusive_order(contract: &mut FusionOrderContract) -> [u8; 32] {
+        testing_env!(get_context("contract.near".parse().unwrap(), 0));
+        contract.add_resolver(accounts(4));
+        
+        testing_env!(get_context(accounts(1), 0));
+        deposit(contract, accounts(1), "token1.near", 1000);
+        let mut order = create_test_order();
+        order.exclusivity_end = Some(500);
+        let order_hash = contract.create_order(order, None);
+        
+        deposit(contract, accounts(3), "token2.near", 1000);
+        deposit(contract, accounts(4), "token2.near", 1000);
+        order_hash
+    }
+
+    #[test]
+    fn test_exclusive_resolver_fills_inside_window() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        let order_hash = create_exclusive_order(&mut contract);
+        
+        testing_env!(get_context(accounts(3), 499));
+        contract.fill_order(order_hash, U128(100), U128(200), None, None);
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 900);
+    }
+
+    #[test]
+    #[should_panic(expected = "Exclusive resolver window")]
+    fn test_other_resolver_blocked_inside_window() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        let order_hash = create_exclusive_order(&mut contract);
+        
+        testing_env!(get_context(accounts(4), 499));
+        contract.fill_order(order_hash, U128(100), U128(200), None, None);
+    }
+
+    #[test]
+    fn test_whitelisted_resolver_fills_after_window() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        let order_hash = create_exclusive_order(&mut contract);
+        
+        testing_env!(get_context(accounts(4), 500));
+        contract.fill_order(order_hash, U128(100), U128(200), None, None);
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 900);
+    }
+
+    #[test]
+    #[should_panic(expected = "Resolver not whitelisted")]
+    fn test_named_resolver_needs_whitelist_after_window() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        let order_hash = create_exclusive_order(&mut contract);
+        
+        testing_env!(get_context(accounts(3), 500));
+        contract.fill_order(order_hash, U128(100), U128(200), None, None);
+    }
+    fn create_predicate_order(contract: &mut FusionOrderContract, predicate: Predicate) -> [u8; 32] {
+        testing_env!(get_context("contract.near".parse().unwrap(), 0));
+        contract.add_predicate_oracle("oracle.near".parse().unwrap());
+        
+        testing_env!(get_context(accounts(1), 0));
+        deposit(contract, accounts(1), "token1.near", 1000);
+        let mut order = create_test_order();
+        order.predicate = Some(predicate);
+        let order_hash = contract.create_order(order, None);
+        
+        deposit(contract, accounts(2), "token2.near", 2000);
+        order_hash
+    }
+
+    fn oracle_above(value: u128) -> Condition {
+        Condition::OracleAbove {
+            oracle: "oracle.near".parse().unwrap(),
+            key: "NEAR/USD".to_string(),
+            value: U128(value),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot match orders with remote predicates")]
+    fn test_match_orders_rejects_remote_predicate() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 0)
+        };
+        let order_hash_a = create_predicate_order(
+            &mut contract,
+            Predicate::And { conditions: vec![oracle_above(100)] },
+        );
+        
+        testing_env!(get_context(accounts(3), 0));
+        deposit(&mut contract, accounts(3), "token2.near", 2200);
+        let mut order_b = create_test_order();
+        order_b.maker = accounts(3);
+        order_b.maker_asset = "token2.near".parse().unwrap();
+        order_b.taker_asset = "token1.near".parse().unwrap();
+        order_b.making_amount = U128(2200);
+        order_b.taking_amount = U128(1000);
+        let order_hash_b = contract.create_order(order_b, None);
+        
+        testing_env!(get_context(accounts(4), 0));
+        contract.match_orders(order_hash_a, order_hash_b, MatchAmounts {
+            making_amount_a: U128(1000),
+            making_amount_b: U128(2200),
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Predicate not satisfied")]
+    fn test_timestamp_predicate_blocks_early_fill() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        let order_hash = create_predicate_order(
+            &mut contract,
+            Predicate::And { conditions: vec![Condition::After { timestamp: 100 }] },
+        );
+        
+        testing_env!(get_context(accounts(2), 99));
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predicate not satisfied")]
+    fn test_nonce_predicate_invalidated_by_maker() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        let order_hash = create_predicate_order(
+            &mut contract,
+            Predicate::And { conditions: vec![Condition::NonceEquals { nonce: 0 }] },
+        );
+        
+        testing_env!(get_context(accounts(1), 0));
+        assert_eq!(contract.increment_nonce(), 1);
+        
+        testing_env!(get_context(accounts(2), 0));
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+    }
+
+    #[test]
+    fn test_oracle_predicate_fills_after_read() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        let predicate = Predicate::And {
+            conditions: vec![Condition::After { timestamp: 0 }, oracle_above(100)],
+        };
+        let order_hash = create_predicate_order(&mut contract, predicate);
+        
+        // The fill waits for the oracle read and leaves the order untouched
+        testing_env!(get_context(accounts(2), 0));
+        contract.fill_order(order_hash, U128(500), U128(1000), None, None);
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 1000);
+        
+        set_promise_results(vec![PromiseResult::Successful(b"\"150\"".to_vec())]);
+        contract.on_predicate_checked(
+            accounts(2),
+            order_hash,
+            FillRequest {
+                fill: FillAmount::Exact(U128(500), U128(1000)),
+                resolver_fee: None,
+                limits: FillLimits::default(),
+            },
+            None,
+        );
+        let (remaining_making, _) = contract.get_remaining_amounts(order_hash);
+        assert_eq!(remaining_making.0, 500);
+    }
+
+    #[test]
+    #[should_panic(expected = "Predicate not satisfied")]
+    fn test_oracle_predicate_below_threshold() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        let order_hash = create_predicate_order(
+            &mut contract,
+            Predicate::And { conditions: vec![oracle_above(100)] },
+        );
+        
+        set_promise_results(vec![PromiseResult::Successful(b"\"99\"".to_vec())]);
+        contract.on_predicate_checked(
+            accounts(2),
+            order_hash,
+            FillRequest {
+                fill: FillAmount::Exact(U128(500), U128(1000)),
+                resolver_fee: None,
+                limits: FillLimits::default(),
+            },
+            None,
+        );
+    }
+
+    #[test]
+    fn test_predicate_or_keeps_results_aligned() {
+        testing_env!(get_context(accounts(1), 0));
+        let predicate = Predicate::Or {
+            conditions: vec![
+                oracle_above(100),
+                Condition::ViewEquals {
+                    contract_id: "dao.near".parse().unwrap(),
+                    method_name: "is_paused".to_string(),
+                    args: "{}".to_string(),
+                    expected: "false".to_string(),
+                },
+            ],
+        };
+        assert_eq!(predicate::remote_calls(&predicate).len(), 2);
+        
+        let results = vec![Some(b"\"50\"".to_vec()), Some(b"false".to_vec())];
+        assert!(predicate::evaluate(&predicate, 0, &results));
+        
+        let results = vec![Some(b"\"50\"".to_vec()), None];
+        assert!(!predicate::evaluate(&predicate, 0, &results));
+    }
+
+    #[test]
+    #[should_panic(expected = "Predicate cannot call this contract")]
+    fn test_predicate_cannot_target_contract() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        create_predicate_order(
+            &mut contract,
+            Predicate::And {
+                conditions: vec![Condition::ViewEquals {
+                    contract_id: "contract.near".parse().unwrap(),
+                    method_name: "add_resolver".to_string(),
+                    args: "{\"resolver\":\"bob.near\"}".to_string(),
+                    expected: "null".to_string(),
+                }],
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Predicate target not allowed")]
+    fn test_predicate_target_must_be_allowlisted() {
+        let mut contract = {
+            testing_env!(get_context(accounts(1), 0));
+            FusionOrderContract::new(accounts(5), 30)
+        };
+        create_predicate_order(
+            &mut contract,
+            Predicate::And {
+                conditions: vec![Condition::OracleAbove {
+                    oracle: "unknown.near".parse().unwrap(),
+                    key: "NEAR/USD".to_string(),
+                    value: U128(100),
+                }],
+            },
+        );
     }
 }
\ No newline at end of file
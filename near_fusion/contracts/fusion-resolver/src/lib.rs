@@ -1,21 +1,188 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::UnorderedMap;
-use near_sdk::json_types::U128;
-use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault, Promise};
-use escrow::{Immutables, FusionOrder, verify_secret};
+use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{I128, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, Gas, near_bindgen, AccountId, PanicOnDefault, Promise, PromiseOrValue, PromiseResult};
+use escrow::{Immutables, FusionOrder, Stage, TimeBoundKind, Timelocks, verify_secret};
 
-const GAS_FOR_FT_TRANSFER: u64 = 10_000_000_000_000;
-const GAS_FOR_CROSS_CONTRACT: u64 = 20_000_000_000_000;
+#[cfg(test)]
+mod tests;
 
-#[derive(BorshDeserialize, BorshSerialize)]
+const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_CROSS_CONTRACT: Gas = Gas(20_000_000_000_000);
+const GAS_FOR_ESCROW_CALLBACK: Gas = Gas(10_000_000_000_000);
+const DEFAULT_PAGE_LIMIT: u64 = 50;
+const MAX_PAGE_LIMIT: u64 = 100;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+/// Longest any escrow stage may be deferred, in seconds (30 days).
+const MAX_TIMELOCK_OFFSET: u64 = 30 * 24 * 3600;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum CrossChainStatus {
+    Initiated,
+    SrcDeployed,
+    DstDeployed,
+    SecretRevealed,
+    Withdrawn,
+    Cancelled,
+    Expired,
+}
+
+impl CrossChainStatus {
+    pub fn can_transition_to(&self, next: CrossChainStatus) -> bool {
+        use CrossChainStatus::*;
+        matches!(
+            (self, next),
+            (Initiated, SrcDeployed)
+                | (Initiated, Expired)
+                | (Initiated, Cancelled)
+                | (SrcDeployed, DstDeployed)
+                | (SrcDeployed, Cancelled)
+                | (DstDeployed, SecretRevealed)
+                | (DstDeployed, Cancelled)
+                | (SecretRevealed, Withdrawn)
+                | (SecretRevealed, Cancelled)
+        )
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            CrossChainStatus::Withdrawn | CrossChainStatus::Cancelled | CrossChainStatus::Expired
+        )
+    }
+}
+
+/// Progress of one escrow's funds once the secret is revealed. The src escrow
+/// pays the resolver contract, which forwards to the counter-order's maker;
+/// the dst escrow pays the maker directly.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum LegStatus {
+    Pending,
+    Withdrawing,
+    Withdrawn,
+    Forwarding,
+    Settled,
+}
+
+/// Tokens that moved through the contract's own balance for one order.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenFlow {
+    pub token: AccountId,
+    pub inflow: U128,
+    pub outflow: U128,
+}
+
+/// Escrow timelock offsets, in seconds after each escrow is deployed.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TimelockPolicy {
+    pub src_withdrawal: u64,
+    pub src_public_withdrawal: u64,
+    pub src_cancellation: u64,
+    pub src_public_cancellation: u64,
+    pub dst_withdrawal: u64,
+    pub dst_public_withdrawal: u64,
+    pub dst_cancellation: u64,
+}
+
+impl Default for TimelockPolicy {
+    fn default() -> Self {
+        Self {
+            src_withdrawal: 3600,
+            src_public_withdrawal: 7200,
+            src_cancellation: 10800,
+            src_public_cancellation: 14400,
+            dst_withdrawal: 1800,
+            dst_public_withdrawal: 3600,
+            dst_cancellation: 7200,
+        }
+    }
+}
+
+impl TimelockPolicy {
+    /// Each side's stages must come in order within `MAX_TIMELOCK_OFFSET`, and
+    /// the dst escrow must become cancellable before the src escrow so the
+    /// resolver is never left exposed.
+    pub fn is_valid(&self) -> bool {
+        self.offsets().iter().all(|offset| *offset <= MAX_TIMELOCK_OFFSET)
+            && self.src_withdrawal < self.src_public_withdrawal
+            && self.src_public_withdrawal < self.src_cancellation
+            && self.src_cancellation < self.src_public_cancellation
+            && self.dst_withdrawal < self.dst_public_withdrawal
+            && self.dst_public_withdrawal < self.dst_cancellation
+            && self.dst_cancellation < self.src_cancellation
+    }
+
+    /// Escrow timelocks compare against the block timestamp, so offsets are
+    /// converted to nanoseconds alongside `deployed_at`.
+    pub fn to_timelocks(&self, deployed_at: u64) -> Timelocks {
+        let nanos = |offset: u64| offset.checked_mul(NANOS_PER_SECOND).expect("Timelock overflow");
+        Timelocks {
+            src_withdrawal: nanos(self.src_withdrawal),
+            src_public_withdrawal: nanos(self.src_public_withdrawal),
+            src_cancellation: nanos(self.src_cancellation),
+            src_public_cancellation: nanos(self.src_public_cancellation),
+            dst_withdrawal: nanos(self.dst_withdrawal),
+            dst_public_withdrawal: nanos(self.dst_public_withdrawal),
+            dst_cancellation: nanos(self.dst_cancellation),
+            deployed_at,
+        }
+    }
+
+    fn offsets(&self) -> [u64; 7] {
+        [
+            self.src_withdrawal,
+            self.src_public_withdrawal,
+            self.src_cancellation,
+            self.src_public_cancellation,
+            self.dst_withdrawal,
+            self.dst_public_withdrawal,
+            self.dst_cancellation,
+        ]
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StatusChange {
+    pub status: CrossChainStatus,
+    pub timestamp: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
 pub struct CrossChainOrder {
     pub src_order: FusionOrder,
     pub dst_order: FusionOrder,
     pub src_escrow: Option<AccountId>,
     pub dst_escrow: Option<AccountId>,
+    /// Immutables each escrow was deployed with, needed to cancel it later.
+    pub src_immutables: Option<Immutables>,
+    pub dst_immutables: Option<Immutables>,
+    /// Set while an escrow deployment is in flight, so only one runs at a time.
+    pub deploying: bool,
     pub secret_hash: [u8; 32],
     pub resolver: AccountId,
-    pub is_completed: bool,
+    pub timelocks: TimelockPolicy,
+    pub src_leg: LegStatus,
+    pub dst_leg: LegStatus,
+    /// Net of these is added to the realised PnL once the order is final.
+    pub flows: Vec<TokenFlow>,
+    pub status: CrossChainStatus,
+    /// Every status the order has been in, oldest first, with the time it was entered.
+    pub history: Vec<StatusChange>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CrossChainOrderView {
+    pub order_hash: [u8; 32],
+    #[serde(flatten)]
+    pub order: CrossChainOrder,
 }
 
 #[near_bindgen]
@@ -25,6 +192,19 @@ pub struct FusionResolver {
     escrow_factory: AccountId,
     fusion_order_contract: AccountId,
     resolver_operators: UnorderedMap<AccountId, bool>,
+    orders_by_status: LookupMap<CrossChainStatus, UnorderedSet<[u8; 32]>>,
+    orders_by_resolver: LookupMap<AccountId, UnorderedSet<[u8; 32]>>,
+    chain_timelocks: LookupMap<String, TimelockPolicy>,
+    inventory: UnorderedMap<AccountId, u128>,
+    /// Part of the inventory held for counter-order makers after a failed forward.
+    owed: LookupMap<AccountId, u128>,
+    /// Part of the inventory that is the resolver's own principal, refunded by
+    /// cancelled dst escrows.
+    principal: LookupMap<AccountId, u128>,
+    realised_pnl: LookupMap<AccountId, i128>,
+    profit_withdrawn: LookupMap<AccountId, u128>,
+    treasury_operators: UnorderedMap<AccountId, bool>,
+    treasury: Option<AccountId>,
 }
 
 #[near_bindgen]
@@ -36,14 +216,30 @@ impl FusionResolver {
             escrow_factory,
             fusion_order_contract,
             resolver_operators: UnorderedMap::new(b"o"),
+            orders_by_status: LookupMap::new(b"s"),
+            orders_by_resolver: LookupMap::new(b"r"),
+            chain_timelocks: LookupMap::new(b"l"),
+            inventory: UnorderedMap::new(b"i"),
+            owed: LookupMap::new(b"d"),
+            principal: LookupMap::new(b"n"),
+            realised_pnl: LookupMap::new(b"p"),
+            profit_withdrawn: LookupMap::new(b"w"),
+            treasury_operators: UnorderedMap::new(b"v"),
+            treasury: None,
         }
     }
 
+    /// Registers a swap. Its escrow windows are `timelocks` if given, else the
+    /// policy registered for `chain_pair`, else the default policy. A per-order
+    /// policy is held to the same bounds as a registered one, as those windows
+    /// bound how long the makers' funds stay locked.
     pub fn initiate_cross_chain_swap(
         &mut self,
         src_order: FusionOrder,
         dst_order: FusionOrder,
         secret_hash: [u8; 32],
+        chain_pair: Option<String>,
+        timelocks: Option<TimelockPolicy>,
     ) -> [u8; 32] {
         let resolver = env::predecessor_account_id();
         assert!(
@@ -51,134 +247,390 @@ impl FusionResolver {
             "Not authorized resolver"
         );
         
+        self._assert_orders_cross(&src_order, &dst_order);
         let order_hash = self._compute_cross_chain_hash(&src_order, &dst_order, &secret_hash);
         
         assert!(
-            !self.cross_chain_orders.get(&order_hash).is_some(),
+            self.cross_chain_orders.get(&order_hash).is_none(),
             "Order already exists"
         );
         
+        let timelocks = match (timelocks, chain_pair) {
+            (Some(policy), _) => {
+                assert!(policy.is_valid(), "Invalid timelock policy");
+                policy
+            }
+            (None, Some(chain_pair)) => self.chain_timelocks.get(&chain_pair).expect("Unknown chain pair"),
+            (None, None) => TimelockPolicy::default(),
+        };
+        
         let cross_chain_order = CrossChainOrder {
             src_order,
             dst_order,
             src_escrow: None,
             dst_escrow: None,
+            src_immutables: None,
+            dst_immutables: None,
+            deploying: false,
             secret_hash,
-            resolver,
-            is_completed: false,
+            resolver: resolver.clone(),
+            timelocks,
+            src_leg: LegStatus::Pending,
+            dst_leg: LegStatus::Pending,
+            flows: vec![],
+            status: CrossChainStatus::Initiated,
+            history: vec![StatusChange {
+                status: CrossChainStatus::Initiated,
+                timestamp: env::block_timestamp(),
+            }],
         };
         
         self.cross_chain_orders.insert(&order_hash, &cross_chain_order);
+        self._index_status(&order_hash, CrossChainStatus::Initiated, true);
+        
+        let mut resolver_orders = self.orders_by_resolver.get(&resolver).unwrap_or_else(|| {
+            UnorderedSet::new([b"r".as_slice(), &env::sha256(resolver.as_bytes())].concat())
+        });
+        resolver_orders.insert(&order_hash);
+        self.orders_by_resolver.insert(&resolver, &resolver_orders);
+        
         order_hash
     }
 
-    pub fn deploy_src_escrow(&mut self, order_hash: [u8; 32], immutables: Immutables) -> Promise {
+    /// Closes an order whose source order expired before any escrow was
+    /// deployed. Not allowed while a deployment started before expiry is
+    /// still in flight, as its escrow would be left untracked.
+    pub fn expire_cross_chain_swap(&mut self, order_hash: [u8; 32]) {
+        let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
+        let expiry = order.src_order.expiry.expect("Order has no expiry");
+        assert!(env::block_timestamp() >= expiry, "Order not expired");
+        assert!(!order.deploying, "Escrow deployment pending");
+        
+        self._set_status(&order_hash, &mut order, CrossChainStatus::Expired);
+        self.cross_chain_orders.insert(&order_hash, &order);
+    }
+
+    /// Deploys the source escrow through the factory. Its immutables are built
+    /// from the stored order, with timelocks taken from the order's policy, so
+    /// the escrow always matches what the maker signed. The escrow address is
+    /// recorded by `on_src_escrow_created`; if the deployment fails the attached
+    /// deposit is refunded and the call can be retried.
+    #[payable]
+    pub fn deploy_src_escrow(&mut self, order_hash: [u8; 32]) -> Promise {
         let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
         let resolver = env::predecessor_account_id();
         
         assert_eq!(order.resolver, resolver, "Only order resolver can deploy");
         assert!(order.src_escrow.is_none(), "Src escrow already deployed");
+        assert!(!order.deploying, "Escrow deployment pending");
+        assert_eq!(order.status, CrossChainStatus::Initiated, "Invalid order status");
+        if let Some(expiry) = order.src_order.expiry {
+            assert!(env::block_timestamp() < expiry, "Order expired");
+        }
+        let immutables = self._create_immutables_from_orders(&order, true, env::block_timestamp());
+        order.deploying = true;
+        self.cross_chain_orders.insert(&order_hash, &order);
         
         Promise::new(self.escrow_factory.clone()).function_call(
-            "create_src_escrow".as_bytes().to_vec(),
-            serde_json::to_vec(&immutables).unwrap(),
+            "create_src_escrow".to_string(),
+            serde_json::json!({
+                "immutables": immutables.clone(),
+            }).to_string().as_bytes().to_vec(),
             env::attached_deposit(),
             GAS_FOR_CROSS_CONTRACT,
         ).then(
             Promise::new(env::current_account_id()).function_call(
-                "on_src_escrow_created".as_bytes().to_vec(),
+                "on_src_escrow_created".to_string(),
                 serde_json::json!({
                     "order_hash": order_hash,
+                    "immutables": immutables,
+                    "deployer": resolver,
+                    "deposit": U128(env::attached_deposit()),
                 }).to_string().as_bytes().to_vec(),
                 0,
-                GAS_FOR_CROSS_CONTRACT,
+                GAS_FOR_ESCROW_CALLBACK,
             )
         )
     }
 
-    pub fn deploy_dst_escrow(
-        &mut self,
-        order_hash: [u8; 32],
-        immutables: Immutables,
-        src_cancellation_timestamp: u64,
-    ) -> Promise {
+    /// Deploys the destination escrow through the factory, built, recorded and
+    /// refunded on failure the same way as `deploy_src_escrow`. Its cancellation
+    /// must not open after the src escrow's.
+    #[payable]
+    pub fn deploy_dst_escrow(&mut self, order_hash: [u8; 32]) -> Promise {
         let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
-        let taker = env::predecessor_account_id();
+        let resolver = env::predecessor_account_id();
+        let immutables = self._create_immutables_from_orders(&order, false, env::block_timestamp());
         
-        assert_eq!(immutables.taker, taker, "Only taker can deploy dst");
+        assert_eq!(order.resolver, resolver, "Only order resolver can deploy");
         assert!(order.dst_escrow.is_none(), "Dst escrow already deployed");
+        assert!(!order.deploying, "Escrow deployment pending");
+        assert_eq!(order.status, CrossChainStatus::SrcDeployed, "Invalid order status");
+        
+        let src_cancellation_timestamp = order
+            .src_immutables
+            .as_ref()
+            .expect("Src escrow not deployed")
+            .timelocks
+            .get_stage_time(Stage::SrcCancellation);
+        assert!(
+            immutables.timelocks.get_stage_time(Stage::DstCancellation) <= src_cancellation_timestamp,
+            "Invalid creation time"
+        );
+        order.deploying = true;
+        self.cross_chain_orders.insert(&order_hash, &order);
         
         Promise::new(self.escrow_factory.clone()).function_call(
-            "create_dst_escrow".as_bytes().to_vec(),
+            "create_dst_escrow".to_string(),
             serde_json::json!({
-                "immutables": immutables,
+                "immutables": immutables.clone(),
                 "src_cancellation_timestamp": src_cancellation_timestamp,
             }).to_string().as_bytes().to_vec(),
             env::attached_deposit(),
             GAS_FOR_CROSS_CONTRACT,
         ).then(
             Promise::new(env::current_account_id()).function_call(
-                "on_dst_escrow_created".as_bytes().to_vec(),
+                "on_dst_escrow_created".to_string(),
                 serde_json::json!({
                     "order_hash": order_hash,
+                    "immutables": immutables,
+                    "deployer": resolver,
+                    "deposit": U128(env::attached_deposit()),
                 }).to_string().as_bytes().to_vec(),
                 0,
-                GAS_FOR_CROSS_CONTRACT,
+                GAS_FOR_ESCROW_CALLBACK,
             )
         )
     }
 
+    /// Reveals the secret to both escrows, which the contract holds as taker.
+    /// The order moves to `SecretRevealed` straight away; each leg is then
+    /// tracked through its own callbacks and the order becomes `Withdrawn` once
+    /// both are settled. Legs that failed are retried by calling this again.
     pub fn execute_cross_chain_fill(
         &mut self,
         order_hash: [u8; 32],
         secret: String,
-        making_amount: U128,
-        taking_amount: U128,
     ) {
-        let order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
+        let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
         let resolver = env::predecessor_account_id();
         
         assert_eq!(order.resolver, resolver, "Only resolver can execute");
-        assert!(!order.is_completed, "Order already completed");
+        assert!(!order.status.is_final(), "Order already completed");
         assert!(verify_secret(&secret, &order.secret_hash), "Invalid secret");
         
-        if let (Some(src_escrow), Some(dst_escrow)) = (&order.src_escrow, &order.dst_escrow) {
-            let immutables = self._create_immutables_from_orders(&order.src_order, &order.dst_order, &order.secret_hash);
-            
-            Promise::new(src_escrow.clone()).function_call(
-                "withdraw".as_bytes().to_vec(),
-                serde_json::json!({
-                    "secret": secret.clone(),
-                    "immutables": immutables.clone(),
-                }).to_string().as_bytes().to_vec(),
-                0,
-                GAS_FOR_CROSS_CONTRACT,
-            );
+        if let (Some(src_escrow), Some(dst_escrow)) = (order.src_escrow.clone(), order.dst_escrow.clone()) {
+            let is_first_reveal = order.status != CrossChainStatus::SecretRevealed;
+            if is_first_reveal {
+                self._set_status(&order_hash, &mut order, CrossChainStatus::SecretRevealed);
+            }
             
-            Promise::new(dst_escrow.clone()).function_call(
-                "withdraw".as_bytes().to_vec(),
+            match order.src_leg {
+                LegStatus::Pending => {
+                    order.src_leg = LegStatus::Withdrawing;
+                    self._withdraw_leg(order_hash, src_escrow, &secret, &order.src_immutables, true);
+                }
+                LegStatus::Withdrawn => {
+                    order.src_leg = LegStatus::Forwarding;
+                    let token = order.src_immutables.as_ref().expect("Src escrow not deployed").token.clone();
+                    self._debit_owed(&token, self._forward_amount(&order));
+                    self._forward_src_funds(order_hash, &order);
+                }
+                _ => {}
+            }
+            if order.dst_leg == LegStatus::Pending {
+                order.dst_leg = LegStatus::Withdrawing;
+                self._withdraw_leg(order_hash, dst_escrow, &secret, &order.dst_immutables, false);
+            }
+            self.cross_chain_orders.insert(&order_hash, &order);
+        } else {
+            panic!("Escrows not deployed");
+        }
+    }
+
+    /// Unwinds a swap whose secret was never used. The dst escrow is cancelled
+    /// once `DstCancellation` starts and the src escrow once `SrcCancellation`
+    /// starts; the order becomes `Cancelled` when both calls succeed. An order
+    /// with no escrow yet is cancelled straight away.
+    pub fn cancel_cross_chain_swap(&mut self, order_hash: [u8; 32]) -> PromiseOrValue<bool> {
+        let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
+        assert_eq!(order.resolver, env::predecessor_account_id(), "Only resolver can cancel");
+        assert!(!order.deploying, "Escrow deployment pending");
+        
+        if order.src_escrow.is_none() && order.dst_escrow.is_none() {
+            self._set_status(&order_hash, &mut order, CrossChainStatus::Cancelled);
+            self.cross_chain_orders.insert(&order_hash, &order);
+            return PromiseOrValue::Value(true);
+        }
+        PromiseOrValue::Promise(self._cancel_escrows(order_hash, &order, false))
+    }
+
+    /// Permissionless variant of `cancel_cross_chain_swap`, available once the
+    /// src escrow's public cancellation window opens.
+    pub fn public_cancel_cross_chain_swap(&mut self, order_hash: [u8; 32]) -> Promise {
+        let order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
+        assert!(order.src_escrow.is_some(), "Escrows not deployed");
+        self._cancel_escrows(order_hash, &order, true)
+    }
+
+    /// Sets the timelock policy used for swaps initiated with `chain_pair`.
+    pub fn set_chain_timelocks(&mut self, chain_pair: String, policy: TimelockPolicy) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can set timelocks"
+        );
+        assert!(policy.is_valid(), "Invalid timelock policy");
+        self.chain_timelocks.insert(&chain_pair, &policy);
+    }
+
+    pub fn get_chain_timelocks(&self, chain_pair: String) -> Option<TimelockPolicy> {
+        self.chain_timelocks.get(&chain_pair)
+    }
+
+    /// Sends realised profit in `token` to the treasury account.
+    pub fn withdraw_profit(&mut self, token: AccountId, amount: U128) -> Promise {
+        assert!(
+            self.treasury_operators.get(&env::predecessor_account_id()).unwrap_or(false),
+            "Not authorized treasury operator"
+        );
+        let treasury = self.treasury.clone().expect("Treasury not set");
+        assert!(amount.0 > 0, "Nothing to withdraw");
+        assert!(amount.0 <= self._withdrawable_profit(&token), "Insufficient profit");
+        
+        self._debit_inventory(&token, amount.0);
+        let withdrawn = self.profit_withdrawn.get(&token).unwrap_or(0);
+        self.profit_withdrawn.insert(&token, &(withdrawn + amount.0));
+        
+        Promise::new(token.clone()).function_call(
+            "ft_transfer".to_string(),
+            serde_json::json!({
+                "receiver_id": treasury,
+                "amount": amount,
+                "memo": "Resolver profit",
+            }).to_string().as_bytes().to_vec(),
+            1,
+            GAS_FOR_FT_TRANSFER,
+        ).then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_profit_withdrawn".to_string(),
                 serde_json::json!({
-                    "secret": secret,
-                    "immutables": immutables,
+                    "token": token,
+                    "amount": amount,
                 }).to_string().as_bytes().to_vec(),
                 0,
-                GAS_FOR_CROSS_CONTRACT,
-            );
-            
-            Promise::new(self.fusion_order_contract.clone()).function_call(
-                "fill_order".as_bytes().to_vec(),
+                GAS_FOR_ESCROW_CALLBACK,
+            )
+        )
+    }
+
+    #[private]
+    pub fn on_profit_withdrawn(&mut self, token: AccountId, amount: U128) -> bool {
+        let withdrawn = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        if !withdrawn {
+            self._credit_inventory(&token, amount.0);
+            let total = self.profit_withdrawn.get(&token).unwrap_or(0);
+            self.profit_withdrawn.insert(&token, &(total - amount.0));
+        }
+        withdrawn
+    }
+
+    /// Sends refunded principal in `token` back to `receiver_id`.
+    pub fn withdraw_principal(&mut self, token: AccountId, amount: U128, receiver_id: AccountId) -> Promise {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can withdraw principal"
+        );
+        assert!(amount.0 > 0, "Nothing to withdraw");
+        let principal = self.principal.get(&token).unwrap_or(0);
+        assert!(amount.0 <= principal, "Insufficient principal");
+        
+        self._debit_inventory(&token, amount.0);
+        self.principal.insert(&token, &(principal - amount.0));
+        
+        Promise::new(token.clone()).function_call(
+            "ft_transfer".to_string(),
+            serde_json::json!({
+                "receiver_id": receiver_id,
+                "amount": amount,
+                "memo": "Resolver principal",
+            }).to_string().as_bytes().to_vec(),
+            1,
+            GAS_FOR_FT_TRANSFER,
+        ).then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_principal_withdrawn".to_string(),
                 serde_json::json!({
-                    "order_hash": order.src_order.hash(),
-                    "making_amount": making_amount,
-                    "taking_amount": taking_amount,
-                    "resolver_fee": null,
+                    "token": token,
+                    "amount": amount,
                 }).to_string().as_bytes().to_vec(),
                 0,
-                GAS_FOR_CROSS_CONTRACT,
-            );
-        } else {
-            panic!("Escrows not deployed");
+                GAS_FOR_ESCROW_CALLBACK,
+            )
+        )
+    }
+
+    #[private]
+    pub fn on_principal_withdrawn(&mut self, token: AccountId, amount: U128) -> bool {
+        let withdrawn = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        if !withdrawn {
+            self._credit_inventory(&token, amount.0);
+            self._credit_principal(&token, amount.0);
         }
+        withdrawn
+    }
+
+    pub fn set_treasury(&mut self, treasury: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can set treasury"
+        );
+        self.treasury = Some(treasury);
+    }
+
+    pub fn add_treasury_operator(&mut self, operator: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can add treasury operators"
+        );
+        self.treasury_operators.insert(&operator, &true);
+    }
+
+    pub fn remove_treasury_operator(&mut self, operator: AccountId) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Only contract can remove treasury operators"
+        );
+        self.treasury_operators.remove(&operator);
+    }
+
+    pub fn get_treasury(&self) -> Option<AccountId> {
+        self.treasury.clone()
+    }
+
+    /// Token balances the contract holds on its own account.
+    pub fn get_inventory(&self) -> Vec<(AccountId, U128)> {
+        self.inventory.iter().map(|(token, amount)| (token, U128(amount))).collect()
+    }
+
+    pub fn get_principal(&self, token: AccountId) -> U128 {
+        U128(self.principal.get(&token).unwrap_or(0))
+    }
+
+    pub fn get_realised_pnl(&self, token: AccountId) -> I128 {
+        I128(self.realised_pnl.get(&token).unwrap_or(0))
+    }
+
+    pub fn get_withdrawable_profit(&self, token: AccountId) -> U128 {
+        U128(self._withdrawable_profit(&token))
+    }
+
+    pub fn get_order_flows(&self, order_hash: [u8; 32]) -> Vec<TokenFlow> {
+        self.cross_chain_orders.get(&order_hash).map(|order| order.flows).unwrap_or_default()
     }
 
     pub fn add_operator(&mut self, operator: AccountId) {
@@ -200,17 +652,390 @@ impl FusionResolver {
     }
 
     #[private]
-    pub fn on_src_escrow_created(&mut self, order_hash: [u8; 32], escrow_address: AccountId) {
+    pub fn on_src_escrow_created(
+        &mut self,
+        order_hash: [u8; 32],
+        immutables: Immutables,
+        deployer: AccountId,
+        deposit: U128,
+    ) -> Option<AccountId> {
+        let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
+        order.deploying = false;
+        let escrow_address = self._deployed_escrow(&deployer, deposit.0);
+        if let Some(escrow_address) = &escrow_address {
+            assert!(order.src_escrow.is_none(), "Src escrow already recorded");
+            order.src_escrow = Some(escrow_address.clone());
+            order.src_immutables = Some(immutables);
+            if order.status == CrossChainStatus::Initiated {
+                self._set_status(&order_hash, &mut order, CrossChainStatus::SrcDeployed);
+            }
+        }
+        self.cross_chain_orders.insert(&order_hash, &order);
+        escrow_address
+    }
+
+    #[private]
+    pub fn on_dst_escrow_created(
+        &mut self,
+        order_hash: [u8; 32],
+        immutables: Immutables,
+        deployer: AccountId,
+        deposit: U128,
+    ) -> Option<AccountId> {
+        let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
+        order.deploying = false;
+        let escrow_address = self._deployed_escrow(&deployer, deposit.0);
+        if let Some(escrow_address) = &escrow_address {
+            assert!(order.dst_escrow.is_none(), "Dst escrow already recorded");
+            order.dst_escrow = Some(escrow_address.clone());
+            order.dst_immutables = Some(immutables);
+            if order.status == CrossChainStatus::SrcDeployed {
+                self._set_status(&order_hash, &mut order, CrossChainStatus::DstDeployed);
+            }
+        }
+        self.cross_chain_orders.insert(&order_hash, &order);
+        escrow_address
+    }
+
+    /// Marks the order cancelled once every escrow cancellation went through.
+    #[private]
+    pub fn on_cross_chain_cancelled(&mut self, order_hash: [u8; 32]) -> bool {
+        let cancelled = (0..env::promise_results_count())
+            .all(|i| matches!(env::promise_result(i), PromiseResult::Successful(_)));
+        if cancelled {
+            let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
+            if !order.status.is_final() {
+                // A cancelled dst escrow refunds its taker, which is this contract.
+                // That is the resolver's own principal coming back, so it is held
+                // but kept out of the order's flows and therefore out of PnL, and
+                // only the contract can take it back out with `withdraw_principal`.
+                if let (Some(immutables), LegStatus::Pending) = (order.dst_immutables.clone(), order.dst_leg) {
+                    self._credit_inventory(&immutables.token, immutables.amount.0);
+                    self._credit_principal(&immutables.token, immutables.amount.0);
+                }
+                self._set_status(&order_hash, &mut order, CrossChainStatus::Cancelled);
+                self.cross_chain_orders.insert(&order_hash, &order);
+            }
+        }
+        cancelled
+    }
+
+    /// Records an escrow withdrawal. Src funds have reached the contract and are
+    /// forwarded on; dst funds went straight to the maker. A failed withdrawal
+    /// puts the leg back to `Pending`.
+    #[private]
+    pub fn on_leg_withdrawn(&mut self, order_hash: [u8; 32], is_src: bool) -> bool {
+        let withdrawn = matches!(env::promise_result(0), PromiseResult::Successful(_));
         let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
-        order.src_escrow = Some(escrow_address);
+        
+        if is_src {
+            order.src_leg = if withdrawn { LegStatus::Forwarding } else { LegStatus::Pending };
+            if withdrawn {
+                let immutables = order.src_immutables.clone().expect("Src escrow not deployed");
+                self._record_inflow(&mut order, &immutables.token, immutables.amount.0);
+                self._forward_src_funds(order_hash, &order);
+            }
+        } else {
+            order.dst_leg = if withdrawn { LegStatus::Settled } else { LegStatus::Pending };
+            self._complete_if_settled(&order_hash, &mut order);
+        }
         self.cross_chain_orders.insert(&order_hash, &order);
+        withdrawn
     }
 
+    /// Records the transfer of src funds to the counter-order's maker. On
+    /// failure the funds go back to inventory, owed to that maker until the
+    /// next retry.
     #[private]
-    pub fn on_dst_escrow_created(&mut self, order_hash: [u8; 32], escrow_address: AccountId) {
+    pub fn on_src_funds_forwarded(&mut self, order_hash: [u8; 32], amount: U128) -> bool {
+        let forwarded = matches!(env::promise_result(0), PromiseResult::Successful(_));
         let mut order = self.cross_chain_orders.get(&order_hash).expect("Order not found");
-        order.dst_escrow = Some(escrow_address);
+        let token = order.src_immutables.as_ref().expect("Src escrow not deployed").token.clone();
+        
+        if forwarded {
+            self._flow_mut(&mut order, &token).outflow.0 += amount.0;
+        } else {
+            self._credit_inventory(&token, amount.0);
+            self._credit_owed(&token, amount.0);
+        }
+        order.src_leg = if forwarded { LegStatus::Settled } else { LegStatus::Withdrawn };
+        self._complete_if_settled(&order_hash, &mut order);
         self.cross_chain_orders.insert(&order_hash, &order);
+        forwarded
+    }
+
+    pub fn get_cross_chain_order(&self, order_hash: [u8; 32]) -> Option<CrossChainOrder> {
+        self.cross_chain_orders.get(&order_hash)
+    }
+
+    pub fn get_orders_by_status(
+        &self,
+        status: CrossChainStatus,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<CrossChainOrderView> {
+        match self.orders_by_status.get(&status) {
+            Some(hashes) => self._paginate(hashes.iter(), from_index, limit),
+            None => vec![],
+        }
+    }
+
+    /// All orders initiated by `resolver`, whatever their status.
+    pub fn get_orders_by_resolver(
+        &self,
+        resolver: AccountId,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<CrossChainOrderView> {
+        match self.orders_by_resolver.get(&resolver) {
+            Some(hashes) => self._paginate(hashes.iter(), from_index, limit),
+            None => vec![],
+        }
+    }
+
+}
+
+impl FusionResolver {
+    fn _cancel_escrows(&self, order_hash: [u8; 32], order: &CrossChainOrder, public: bool) -> Promise {
+        assert!(!order.status.is_final(), "Order already completed");
+        assert!(!order.deploying, "Escrow deployment pending");
+        
+        let mut cancellation: Option<Promise> = None;
+        if let (Some(dst_escrow), Some(immutables)) = (&order.dst_escrow, &order.dst_immutables) {
+            assert!(
+                !immutables.timelocks.is_stage_time(Stage::DstCancellation, TimeBoundKind::Before),
+                "Too early"
+            );
+            cancellation = Some(self._escrow_call(dst_escrow, "cancel", immutables));
+        }
+        if let (Some(src_escrow), Some(immutables)) = (&order.src_escrow, &order.src_immutables) {
+            let (stage, method) = if public {
+                (Stage::SrcPublicCancellation, "public_cancel")
+            } else {
+                (Stage::SrcCancellation, "cancel")
+            };
+            assert!(!immutables.timelocks.is_stage_time(stage, TimeBoundKind::Before), "Too early");
+            let src_cancel = self._escrow_call(src_escrow, method, immutables);
+            cancellation = Some(match cancellation {
+                Some(dst_cancel) => dst_cancel.and(src_cancel),
+                None => src_cancel,
+            });
+        }
+        
+        cancellation.expect("Escrows not deployed").then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_cross_chain_cancelled".to_string(),
+                serde_json::json!({
+                    "order_hash": order_hash,
+                }).to_string().as_bytes().to_vec(),
+                0,
+                GAS_FOR_ESCROW_CALLBACK,
+            )
+        )
+    }
+
+    fn _withdraw_leg(
+        &self,
+        order_hash: [u8; 32],
+        escrow: AccountId,
+        secret: &str,
+        immutables: &Option<Immutables>,
+        is_src: bool,
+    ) -> Promise {
+        Promise::new(escrow).function_call(
+            "withdraw".to_string(),
+            serde_json::json!({
+                "secret": secret,
+                "immutables": immutables.as_ref().expect("Escrow not deployed"),
+            }).to_string().as_bytes().to_vec(),
+            0,
+            GAS_FOR_CROSS_CONTRACT,
+        ).then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_leg_withdrawn".to_string(),
+                serde_json::json!({
+                    "order_hash": order_hash,
+                    "is_src": is_src,
+                }).to_string().as_bytes().to_vec(),
+                0,
+                GAS_FOR_ESCROW_CALLBACK,
+            )
+        )
+    }
+
+    /// Pays the counter-order's maker what it asked for; anything the src maker
+    /// gave beyond that stays in inventory as the resolver's spread.
+    fn _forward_src_funds(&mut self, order_hash: [u8; 32], order: &CrossChainOrder) -> Promise {
+        let immutables = order.src_immutables.as_ref().expect("Src escrow not deployed");
+        let amount = self._forward_amount(order);
+        self._debit_inventory(&immutables.token, amount);
+        
+        Promise::new(immutables.token.clone()).function_call(
+            "ft_transfer".to_string(),
+            serde_json::json!({
+                "receiver_id": order.dst_order.maker,
+                "amount": U128(amount),
+                "memo": "Cross-chain fill",
+            }).to_string().as_bytes().to_vec(),
+            1,
+            GAS_FOR_FT_TRANSFER,
+        ).then(
+            Promise::new(env::current_account_id()).function_call(
+                "on_src_funds_forwarded".to_string(),
+                serde_json::json!({
+                    "order_hash": order_hash,
+                    "amount": U128(amount),
+                }).to_string().as_bytes().to_vec(),
+                0,
+                GAS_FOR_ESCROW_CALLBACK,
+            )
+        )
+    }
+
+    fn _forward_amount(&self, order: &CrossChainOrder) -> u128 {
+        let immutables = order.src_immutables.as_ref().expect("Src escrow not deployed");
+        order.dst_order.taking_amount.0.min(immutables.amount.0)
+    }
+
+    fn _complete_if_settled(&mut self, order_hash: &[u8; 32], order: &mut CrossChainOrder) {
+        if order.src_leg == LegStatus::Settled
+            && order.dst_leg == LegStatus::Settled
+            && order.status == CrossChainStatus::SecretRevealed
+        {
+            self._set_status(order_hash, order, CrossChainStatus::Withdrawn);
+        }
+    }
+
+    fn _escrow_call(&self, escrow: &AccountId, method: &str, immutables: &Immutables) -> Promise {
+        Promise::new(escrow.clone()).function_call(
+            method.to_string(),
+            serde_json::json!({
+                "immutables": immutables,
+            }).to_string().as_bytes().to_vec(),
+            0,
+            GAS_FOR_CROSS_CONTRACT,
+        )
+    }
+
+    fn _set_status(&mut self, order_hash: &[u8; 32], order: &mut CrossChainOrder, status: CrossChainStatus) {
+        assert!(order.status.can_transition_to(status), "Invalid status transition");
+        self._index_status(order_hash, order.status, false);
+        self._index_status(order_hash, status, true);
+        order.status = status;
+        order.history.push(StatusChange {
+            status,
+            timestamp: env::block_timestamp(),
+        });
+        
+        if status.is_final() {
+            for flow in &order.flows {
+                let pnl = self.realised_pnl.get(&flow.token).unwrap_or(0);
+                let net = flow.inflow.0 as i128 - flow.outflow.0 as i128;
+                self.realised_pnl.insert(&flow.token, &(pnl + net));
+            }
+        }
+    }
+
+    fn _flow_mut<'a>(&self, order: &'a mut CrossChainOrder, token: &AccountId) -> &'a mut TokenFlow {
+        let index = match order.flows.iter().position(|flow| &flow.token == token) {
+            Some(index) => index,
+            None => {
+                order.flows.push(TokenFlow {
+                    token: token.clone(),
+                    inflow: U128(0),
+                    outflow: U128(0),
+                });
+                order.flows.len() - 1
+            }
+        };
+        &mut order.flows[index]
+    }
+
+    fn _record_inflow(&mut self, order: &mut CrossChainOrder, token: &AccountId, amount: u128) {
+        self._flow_mut(order, token).inflow.0 += amount;
+        self._credit_inventory(token, amount);
+    }
+
+    fn _credit_inventory(&mut self, token: &AccountId, amount: u128) {
+        let balance = self.inventory.get(token).unwrap_or(0);
+        self.inventory.insert(token, &(balance + amount));
+    }
+
+    fn _debit_inventory(&mut self, token: &AccountId, amount: u128) {
+        let balance = self.inventory.get(token).unwrap_or(0);
+        assert!(balance >= amount, "Insufficient inventory");
+        self.inventory.insert(token, &(balance - amount));
+    }
+
+    fn _credit_principal(&mut self, token: &AccountId, amount: u128) {
+        let principal = self.principal.get(token).unwrap_or(0);
+        self.principal.insert(token, &(principal + amount));
+    }
+
+    fn _credit_owed(&mut self, token: &AccountId, amount: u128) {
+        let owed = self.owed.get(token).unwrap_or(0);
+        self.owed.insert(token, &(owed + amount));
+    }
+
+    fn _debit_owed(&mut self, token: &AccountId, amount: u128) {
+        let owed = self.owed.get(token).unwrap_or(0);
+        assert!(owed >= amount, "Owed amount underflow");
+        self.owed.insert(token, &(owed - amount));
+    }
+
+    /// Realised profit not yet withdrawn, limited to what is held and neither
+    /// owed to a counter-order's maker nor refunded principal.
+    fn _withdrawable_profit(&self, token: &AccountId) -> u128 {
+        let realised = self.realised_pnl.get(token).unwrap_or(0);
+        let withdrawn = self.profit_withdrawn.get(token).unwrap_or(0) as i128;
+        let profit = (realised - withdrawn).max(0) as u128;
+        let held = self.inventory.get(token).unwrap_or(0);
+        let reserved = self.owed.get(token).unwrap_or(0) + self.principal.get(token).unwrap_or(0);
+        profit.min(held.saturating_sub(reserved))
+    }
+
+    fn _index_status(&mut self, order_hash: &[u8; 32], status: CrossChainStatus, insert: bool) {
+        let mut status_orders = self.orders_by_status.get(&status).unwrap_or_else(|| {
+            UnorderedSet::new([b"t".as_slice(), &[status as u8]].concat())
+        });
+        if insert {
+            status_orders.insert(order_hash);
+        } else {
+            status_orders.remove(order_hash);
+        }
+        self.orders_by_status.insert(&status, &status_orders);
+    }
+
+    fn _paginate(
+        &self,
+        hashes: impl Iterator<Item = [u8; 32]>,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<CrossChainOrderView> {
+        let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+        hashes
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit as usize)
+            .filter_map(|order_hash| {
+                self.cross_chain_orders.get(&order_hash).map(|order| CrossChainOrderView { order_hash, order })
+            })
+            .collect()
+    }
+
+    /// Reads the escrow account returned by the factory. A failed deployment
+    /// leaves the order as it was, so it can be retried, and refunds the deposit.
+    fn _deployed_escrow(&self, deployer: &AccountId, deposit: u128) -> Option<AccountId> {
+        match env::promise_result(0) {
+            PromiseResult::Successful(result) => {
+                Some(serde_json::from_slice(&result).expect("Invalid escrow address"))
+            }
+            _ => {
+                if deposit > 0 {
+                    Promise::new(deployer.clone()).transfer(deposit);
+                }
+                None
+            }
+        }
     }
 
     fn _compute_cross_chain_hash(
@@ -221,38 +1046,42 @@ impl FusionResolver {
     ) -> [u8; 32] {
         use sha3::{Digest, Keccak256};
         let mut hasher = Keccak256::new();
-        hasher.update(&src_order.hash());
-        hasher.update(&dst_order.hash());
+        hasher.update(src_order.hash());
+        hasher.update(dst_order.hash());
         hasher.update(secret_hash);
         hasher.finalize().into()
     }
 
-    fn _create_immutables_from_orders(
-        &self,
-        src_order: &FusionOrder,
-        dst_order: &FusionOrder,
-        secret_hash: &[u8; 32],
-    ) -> Immutables {
-        use escrow::Timelocks;
+    /// Immutables for one side of the swap. Both escrows share the src order
+    /// hash and the hashlock, and name this contract as taker so it can withdraw
+    /// and cancel them; the src escrow holds the maker's tokens and the dst
+    /// escrow holds the counter-order's tokens for the maker.
+    /// The dst order must give what the src order asks for and ask for no more
+    /// than the src order gives, or the resolver cannot fund both sides.
+    fn _assert_orders_cross(&self, src_order: &FusionOrder, dst_order: &FusionOrder) {
+        assert_eq!(dst_order.maker_asset, src_order.taker_asset, "Orders do not cross");
+        assert_eq!(src_order.maker_asset, dst_order.taker_asset, "Orders do not cross");
+        assert!(dst_order.making_amount.0 >= src_order.taking_amount.0, "Dst order gives too little");
+        assert!(src_order.making_amount.0 >= dst_order.taking_amount.0, "Src order gives too little");
+    }
+
+    fn _create_immutables_from_orders(&self, order: &CrossChainOrder, is_src: bool, deployed_at: u64) -> Immutables {
+        self._assert_orders_cross(&order.src_order, &order.dst_order);
+        let (token, amount) = if is_src {
+            (order.src_order.maker_asset.clone(), order.src_order.making_amount)
+        } else {
+            (order.dst_order.maker_asset.clone(), order.dst_order.making_amount)
+        };
         
         Immutables {
-            order_hash: src_order.hash(),
-            hashlock: *secret_hash,
-            maker: src_order.maker.clone(),
-            taker: dst_order.maker.clone(),
-            token: src_order.maker_asset.clone(),
-            amount: src_order.making_amount.clone(),
+            order_hash: order.src_order.hash(),
+            hashlock: order.secret_hash,
+            maker: order.src_order.maker.clone(),
+            taker: env::current_account_id(),
+            token,
+            amount,
             safety_deposit: U128(0),
-            timelocks: Timelocks {
-                src_withdrawal: 3600,
-                src_public_withdrawal: 7200,
-                src_cancellation: 10800,
-                src_public_cancellation: 14400,
-                dst_withdrawal: 1800,
-                dst_public_withdrawal: 3600,
-                dst_cancellation: 7200,
-                deployed_at: env::block_timestamp(),
-            },
+            timelocks: order.timelocks.to_timelocks(deployed_at),
         }
     }
 }
\ No newline at end of file
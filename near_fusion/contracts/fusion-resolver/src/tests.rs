@@ -0,0 +1,754 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod tests {
+    use crate::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, VMContext, Balance};
+
+    const NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+    const SECOND: u64 = 1_000_000_000;
+
+    fn get_context(predecessor_account_id: AccountId, block_timestamp: u64) -> VMContext {
+        VMContextBuilder::new()
+            .current_account_id("resolver.near".parse().unwrap())
+            .predecessor_account_id(predecessor_account_id)
+            .block_timestamp(block_timestamp)
+            .attached_deposit(NEAR) // Covers escrow deployment
+            .build()
+    }
+
+    fn set_promise_results(results: Vec<PromiseResult>) {
+        testing_env!(
+            get_context("resolver.near".parse().unwrap(), env::block_timestamp()),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            results
+        );
+    }
+
+    fn create_test_order(maker: AccountId, salt: u8) -> FusionOrder {
+        FusionOrder {
+            order_hash: [salt; 32],
+            maker,
+            resolver: None,
+            maker_asset: "token1.near".parse().unwrap(),
+            taker_asset: "token2.near".parse().unwrap(),
+            making_amount: U128(1000),
+            taking_amount: U128(2000),
+            maker_traits: 0,
+            salt: [salt; 32],
+            integrator_fee: None,
+            exclusivity_end: None,
+            predicate: None,
+            expiry: None,
+        }
+    }
+
+    /// The counter-order: sells the src order's taker asset for its maker asset.
+    fn create_dst_order(maker: AccountId, salt: u8) -> FusionOrder {
+        FusionOrder {
+            maker_asset: "token2.near".parse().unwrap(),
+            taker_asset: "token1.near".parse().unwrap(),
+            making_amount: U128(2000),
+            taking_amount: U128(1000),
+            ..create_test_order(maker, salt)
+        }
+    }
+
+    fn secret_hash(secret: &str) -> [u8; 32] {
+        use sha3::{Digest, Keccak256};
+        Keccak256::digest(secret.as_bytes()).into()
+    }
+
+    fn setup() -> (FusionResolver, [u8; 32]) {
+        setup_with_orders(create_test_order(accounts(1), 1), create_dst_order(accounts(2), 2))
+    }
+
+    fn setup_with_orders(src_order: FusionOrder, dst_order: FusionOrder) -> (FusionResolver, [u8; 32]) {
+        testing_env!(get_context("resolver.near".parse().unwrap(), 0));
+        let mut contract = FusionResolver::new("factory.near".parse().unwrap(), "fusion.near".parse().unwrap());
+        contract.add_operator(accounts(0));
+        
+        testing_env!(get_context(accounts(0), 0));
+        let order_hash = contract.initiate_cross_chain_swap(src_order, dst_order, secret_hash("secret"), None, None);
+        (contract, order_hash)
+    }
+
+    fn test_immutables(contract: &FusionResolver, order_hash: [u8; 32]) -> Immutables {
+        let order = contract.get_cross_chain_order(order_hash).unwrap();
+        contract._create_immutables_from_orders(&order, true, 0)
+    }
+
+    fn deploy_escrows(contract: &mut FusionResolver, order_hash: [u8; 32]) {
+        let order = contract.get_cross_chain_order(order_hash).unwrap();
+        set_promise_results(vec![PromiseResult::Successful(b"\"src-escrow.factory.near\"".to_vec())]);
+        contract.on_src_escrow_created(order_hash, test_immutables(contract, order_hash), accounts(0), U128(NEAR));
+        set_promise_results(vec![PromiseResult::Successful(b"\"dst-escrow.factory.near\"".to_vec())]);
+        let dst_immutables = contract._create_immutables_from_orders(&order, false, 0);
+        contract.on_dst_escrow_created(order_hash, dst_immutables, accounts(2), U128(NEAR));
+    }
+
+    fn settle_legs(contract: &mut FusionResolver, order_hash: [u8; 32]) {
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_leg_withdrawn(order_hash, false);
+        contract.on_leg_withdrawn(order_hash, true);
+        contract.on_src_funds_forwarded(order_hash, U128(1000));
+    }
+
+    fn statuses(contract: &FusionResolver, order_hash: [u8; 32]) -> Vec<CrossChainStatus> {
+        contract
+            .get_cross_chain_order(order_hash)
+            .unwrap()
+            .history
+            .iter()
+            .map(|change| change.status)
+            .collect()
+    }
+
+    #[test]
+    fn test_src_escrow_created_records_factory_address() {
+        let (mut contract, order_hash) = setup();
+        
+        set_promise_results(vec![PromiseResult::Successful(b"\"src-escrow.factory.near\"".to_vec())]);
+        let escrow = contract.on_src_escrow_created(order_hash, test_immutables(&contract, order_hash), accounts(0), U128(NEAR));
+        
+        assert_eq!(escrow, Some("src-escrow.factory.near".parse().unwrap()));
+        let order = contract.cross_chain_orders.get(&order_hash).unwrap();
+        assert_eq!(order.src_escrow, escrow);
+        assert!(order.dst_escrow.is_none());
+        assert_eq!(order.status, CrossChainStatus::SrcDeployed);
+    }
+
+    #[test]
+    fn test_failed_deployment_is_retryable() {
+        let (mut contract, order_hash) = setup();
+        testing_env!(get_context(accounts(0), 0));
+        contract.deploy_src_escrow(order_hash);
+        assert!(contract.get_cross_chain_order(order_hash).unwrap().deploying);
+        
+        set_promise_results(vec![PromiseResult::Failed]);
+        let immutables = test_immutables(&contract, order_hash);
+        assert!(contract.on_src_escrow_created(order_hash, immutables, accounts(0), U128(NEAR)).is_none());
+        assert!(contract.cross_chain_orders.get(&order_hash).unwrap().src_escrow.is_none());
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().status, CrossChainStatus::Initiated);
+        
+        // The resolver can deploy again once the failure is recorded
+        testing_env!(get_context(accounts(0), 0));
+        contract.deploy_src_escrow(order_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow deployment pending")]
+    fn test_cannot_deploy_twice_while_pending() {
+        let (mut contract, order_hash) = setup();
+        testing_env!(get_context(accounts(0), 0));
+        contract.deploy_src_escrow(order_hash);
+        contract.deploy_src_escrow(order_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Src escrow already recorded")]
+    fn test_escrow_callback_does_not_overwrite() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        set_promise_results(vec![PromiseResult::Successful(b"\"other.factory.near\"".to_vec())]);
+        contract.on_src_escrow_created(order_hash, test_immutables(&contract, order_hash), accounts(0), U128(NEAR));
+    }
+
+    #[test]
+    fn test_dst_escrow_created_records_factory_address() {
+        let (mut contract, order_hash) = setup();
+        
+        deploy_escrows(&mut contract, order_hash);
+        
+        let order = contract.cross_chain_orders.get(&order_hash).unwrap();
+        assert_eq!(order.dst_escrow, Some("dst-escrow.factory.near".parse().unwrap()));
+        assert_eq!(order.status, CrossChainStatus::DstDeployed);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid order status")]
+    fn test_dst_deploy_requires_src_escrow() {
+        let (mut contract, order_hash) = setup();
+        
+        testing_env!(get_context(accounts(0), 0));
+        contract.deploy_dst_escrow(order_hash);
+    }
+
+    #[test]
+    fn test_lifecycle_records_each_transition() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(0), 500));
+        contract.execute_cross_chain_fill(order_hash, "secret".to_string());
+        let order = contract.get_cross_chain_order(order_hash).unwrap();
+        assert_eq!(order.status, CrossChainStatus::SecretRevealed);
+        assert_eq!(order.history.last().unwrap().timestamp, 500);
+        
+        settle_legs(&mut contract, order_hash);
+        assert_eq!(
+            statuses(&contract, order_hash),
+            vec![
+                CrossChainStatus::Initiated,
+                CrossChainStatus::SrcDeployed,
+                CrossChainStatus::DstDeployed,
+                CrossChainStatus::SecretRevealed,
+                CrossChainStatus::Withdrawn,
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Order already completed")]
+    fn test_withdrawn_order_cannot_execute_again() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(0), 500));
+        contract.execute_cross_chain_fill(order_hash, "secret".to_string());
+        settle_legs(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(0), 600));
+        contract.execute_cross_chain_fill(order_hash, "secret".to_string());
+    }
+
+    #[test]
+    fn test_expire_undeployed_order() {
+        let mut src_order = create_test_order(accounts(1), 1);
+        src_order.expiry = Some(1000);
+        let (mut contract, order_hash) = setup_with_orders(src_order, create_dst_order(accounts(2), 2));
+        
+        testing_env!(get_context(accounts(4), 1000));
+        contract.expire_cross_chain_swap(order_hash);
+        
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().status, CrossChainStatus::Expired);
+        assert_eq!(contract.get_orders_by_status(CrossChainStatus::Expired, None, None).len(), 1);
+        assert!(contract.get_orders_by_status(CrossChainStatus::Initiated, None, None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow deployment pending")]
+    fn test_cannot_expire_during_deployment() {
+        let mut src_order = create_test_order(accounts(1), 1);
+        src_order.expiry = Some(1000);
+        let (mut contract, order_hash) = setup_with_orders(src_order, create_dst_order(accounts(2), 2));
+        testing_env!(get_context(accounts(0), 999));
+        contract.deploy_src_escrow(order_hash);
+        
+        testing_env!(get_context(accounts(4), 1000));
+        contract.expire_cross_chain_swap(order_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Escrow deployment pending")]
+    fn test_cannot_cancel_during_deployment() {
+        let (mut contract, order_hash) = setup();
+        testing_env!(get_context(accounts(0), 0));
+        contract.deploy_src_escrow(order_hash);
+        contract.cancel_cross_chain_swap(order_hash);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid status transition")]
+    fn test_cannot_expire_deployed_order() {
+        let mut src_order = create_test_order(accounts(1), 1);
+        src_order.expiry = Some(1000);
+        let (mut contract, order_hash) = setup_with_orders(src_order, create_dst_order(accounts(2), 2));
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(4), 1000));
+        contract.expire_cross_chain_swap(order_hash);
+    }
+
+    #[test]
+    fn test_orders_by_status_and_resolver() {
+        let (mut contract, first_hash) = setup();
+        let second_hash = contract.initiate_cross_chain_swap(
+            create_test_order(accounts(1), 3),
+            create_dst_order(accounts(2), 4),
+            [8; 32],
+            None,
+            None,
+        );
+        deploy_escrows(&mut contract, first_hash);
+        
+        let deployed = contract.get_orders_by_status(CrossChainStatus::DstDeployed, None, None);
+        assert_eq!(deployed.len(), 1);
+        assert_eq!(deployed[0].order_hash, first_hash);
+        
+        let initiated = contract.get_orders_by_status(CrossChainStatus::Initiated, None, None);
+        assert_eq!(initiated.len(), 1);
+        assert_eq!(initiated[0].order_hash, second_hash);
+        
+        assert_eq!(contract.get_orders_by_resolver(accounts(0), None, None).len(), 2);
+        assert_eq!(contract.get_orders_by_resolver(accounts(0), Some(1), Some(10)).len(), 1);
+        assert!(contract.get_orders_by_resolver(accounts(1), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_cancel_undeployed_order() {
+        let (mut contract, order_hash) = setup();
+        
+        contract.cancel_cross_chain_swap(order_hash);
+        
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().status, CrossChainStatus::Cancelled);
+    }
+
+    #[test]
+    #[should_panic(expected = "Too early")]
+    fn test_cancel_before_cancellation_window() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        // Past DstCancellation but not yet SrcCancellation
+        testing_env!(get_context(accounts(0), 8000 * SECOND));
+        contract.cancel_cross_chain_swap(order_hash);
+    }
+
+    #[test]
+    fn test_cancel_deployed_swap() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(0), 11000 * SECOND));
+        contract.cancel_cross_chain_swap(order_hash);
+        
+        // A failed leg leaves the swap open so the cancellation can be retried
+        set_promise_results(vec![PromiseResult::Failed, PromiseResult::Successful(vec![])]);
+        assert!(!contract.on_cross_chain_cancelled(order_hash));
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().status, CrossChainStatus::DstDeployed);
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]);
+        assert!(contract.on_cross_chain_cancelled(order_hash));
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().status, CrossChainStatus::Cancelled);
+    }
+
+    #[test]
+    fn test_cancelled_swap_realises_no_profit() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        let token: AccountId = "token2.near".parse().unwrap();
+        
+        testing_env!(get_context(accounts(0), 11000 * SECOND));
+        contract.cancel_cross_chain_swap(order_hash);
+        set_promise_results(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]);
+        assert!(contract.on_cross_chain_cancelled(order_hash));
+        
+        // The dst refund is held, but it is principal and not profit
+        assert_eq!(contract.get_inventory(), vec![(token.clone(), U128(2000))]);
+        assert_eq!(contract.get_principal(token.clone()).0, 2000);
+        assert!(contract.get_order_flows(order_hash).is_empty());
+        assert_eq!(contract.get_realised_pnl(token.clone()).0, 0);
+        assert_eq!(contract.get_withdrawable_profit(token).0, 0);
+    }
+
+    fn cancel_swap() -> FusionResolver {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        testing_env!(get_context(accounts(0), 11000 * SECOND));
+        contract.cancel_cross_chain_swap(order_hash);
+        set_promise_results(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]);
+        contract.on_cross_chain_cancelled(order_hash);
+        contract
+    }
+
+    #[test]
+    fn test_withdraw_refunded_principal() {
+        let mut contract = cancel_swap();
+        let token: AccountId = "token2.near".parse().unwrap();
+        
+        testing_env!(get_context("resolver.near".parse().unwrap(), 0));
+        contract.withdraw_principal(token.clone(), U128(1500), accounts(0));
+        assert_eq!(contract.get_principal(token.clone()).0, 500);
+        assert_eq!(contract.get_inventory(), vec![(token.clone(), U128(500))]);
+        
+        // A failed transfer puts the principal back
+        set_promise_results(vec![PromiseResult::Failed]);
+        assert!(!contract.on_principal_withdrawn(token.clone(), U128(1500)));
+        assert_eq!(contract.get_principal(token.clone()).0, 2000);
+        assert_eq!(contract.get_inventory(), vec![(token, U128(2000))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only contract can withdraw principal")]
+    fn test_withdraw_principal_requires_contract() {
+        let mut contract = cancel_swap();
+        testing_env!(get_context(accounts(0), 0));
+        contract.withdraw_principal("token2.near".parse().unwrap(), U128(1), accounts(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient principal")]
+    fn test_cannot_withdraw_more_than_principal() {
+        let mut contract = cancel_swap();
+        testing_env!(get_context("resolver.near".parse().unwrap(), 0));
+        contract.withdraw_principal("token2.near".parse().unwrap(), U128(2001), accounts(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only resolver can cancel")]
+    fn test_cancel_requires_resolver() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(4), 11000 * SECOND));
+        contract.cancel_cross_chain_swap(order_hash);
+    }
+
+    #[test]
+    fn test_public_cancel_after_public_window() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(4), 14400 * SECOND));
+        contract.public_cancel_cross_chain_swap(order_hash);
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![]), PromiseResult::Successful(vec![])]);
+        contract.on_cross_chain_cancelled(order_hash);
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().status, CrossChainStatus::Cancelled);
+    }
+
+    #[test]
+    #[should_panic(expected = "Too early")]
+    fn test_public_cancel_before_public_window() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(4), 11000 * SECOND));
+        contract.public_cancel_cross_chain_swap(order_hash);
+    }
+
+    #[test]
+    fn test_default_timelocks_are_in_nanoseconds() {
+        let timelocks = TimelockPolicy::default().to_timelocks(5 * SECOND);
+        
+        assert_eq!(timelocks.get_stage_time(Stage::SrcWithdrawal), 3605 * SECOND);
+        assert_eq!(timelocks.get_stage_time(Stage::DstCancellation), 7205 * SECOND);
+    }
+
+    #[test]
+    fn test_chain_pair_timelocks() {
+        let (mut contract, _) = setup();
+        let policy = TimelockPolicy {
+            src_withdrawal: 60,
+            src_public_withdrawal: 120,
+            src_cancellation: 600,
+            src_public_cancellation: 900,
+            dst_withdrawal: 30,
+            dst_public_withdrawal: 90,
+            dst_cancellation: 300,
+        };
+        testing_env!(get_context("resolver.near".parse().unwrap(), 0));
+        contract.set_chain_timelocks("near:ethereum".to_string(), policy.clone());
+        
+        testing_env!(get_context(accounts(0), 0));
+        let order_hash = contract.initiate_cross_chain_swap(
+            create_test_order(accounts(1), 3),
+            create_dst_order(accounts(2), 4),
+            [8; 32],
+            Some("near:ethereum".to_string()),
+            None,
+        );
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().timelocks, policy);
+        assert_eq!(contract.get_chain_timelocks("near:ethereum".to_string()), Some(policy));
+        assert_eq!(contract.get_chain_timelocks("near:solana".to_string()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown chain pair")]
+    fn test_unknown_chain_pair_rejected() {
+        let (mut contract, _) = setup();
+        contract.initiate_cross_chain_swap(
+            create_test_order(accounts(1), 3),
+            create_dst_order(accounts(2), 4),
+            [8; 32],
+            Some("near:solana".to_string()),
+            None,
+        );
+    }
+
+    #[test]
+    fn test_per_order_timelocks() {
+        let (mut contract, _) = setup();
+        let policy = TimelockPolicy {
+            src_cancellation: 20000,
+            src_public_cancellation: 30000,
+            ..TimelockPolicy::default()
+        };
+        
+        // A per-order policy takes precedence over the chain pair's
+        testing_env!(get_context("resolver.near".parse().unwrap(), 0));
+        contract.set_chain_timelocks("near:ethereum".to_string(), TimelockPolicy::default());
+        testing_env!(get_context(accounts(0), 0));
+        let order_hash = contract.initiate_cross_chain_swap(
+            create_test_order(accounts(1), 3),
+            create_dst_order(accounts(2), 4),
+            [8; 32],
+            Some("near:ethereum".to_string()),
+            Some(policy.clone()),
+        );
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().timelocks, policy);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid timelock policy")]
+    fn test_per_order_timelocks_are_bounded() {
+        let (mut contract, _) = setup();
+        // Keeps the maker's funds locked for far longer than any admin policy may
+        let policy = TimelockPolicy {
+            src_public_cancellation: MAX_TIMELOCK_OFFSET + 1,
+            ..TimelockPolicy::default()
+        };
+        contract.initiate_cross_chain_swap(
+            create_test_order(accounts(1), 3),
+            create_dst_order(accounts(2), 4),
+            [8; 32],
+            None,
+            Some(policy),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid timelock policy")]
+    fn test_dst_cancellation_must_precede_src_cancellation() {
+        let (mut contract, _) = setup();
+        let policy = TimelockPolicy {
+            dst_cancellation: 12000,
+            ..TimelockPolicy::default()
+        };
+        
+        testing_env!(get_context("resolver.near".parse().unwrap(), 0));
+        contract.set_chain_timelocks("near:ethereum".to_string(), policy);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid timelock policy")]
+    fn test_timelock_offsets_are_bounded() {
+        let (mut contract, _) = setup();
+        // Ordered, but the last stage would only open after the nanosecond
+        // conversion overflows
+        let policy = TimelockPolicy {
+            src_public_cancellation: u64::MAX / 2,
+            ..TimelockPolicy::default()
+        };
+        
+        testing_env!(get_context("resolver.near".parse().unwrap(), 0));
+        contract.set_chain_timelocks("near:ethereum".to_string(), policy);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid creation time")]
+    fn test_late_dst_deployment_rejected() {
+        let (mut contract, order_hash) = setup();
+        set_promise_results(vec![PromiseResult::Successful(b"\"src-escrow.factory.near\"".to_vec())]);
+        contract.on_src_escrow_created(order_hash, test_immutables(&contract, order_hash), accounts(0), U128(NEAR));
+        
+        // Deploying dst an hour in would open its cancellation after the src escrow's
+        testing_env!(get_context(accounts(0), 3601 * SECOND));
+        contract.deploy_dst_escrow(order_hash);
+    }
+
+    #[test]
+    fn test_immutables_derived_from_stored_order() {
+        let mut dst_order = create_dst_order(accounts(2), 2);
+        dst_order.making_amount = U128(2100);
+        let (contract, order_hash) = setup_with_orders(create_test_order(accounts(1), 1), dst_order);
+        let order = contract.get_cross_chain_order(order_hash).unwrap();
+        
+        let src = contract._create_immutables_from_orders(&order, true, 7);
+        assert_eq!(src.order_hash, order.src_order.hash());
+        assert_eq!(src.hashlock, secret_hash("secret"));
+        assert_eq!(src.maker, accounts(1));
+        assert_eq!(src.taker, "resolver.near".parse::<AccountId>().unwrap());
+        assert_eq!(src.token, "token1.near".parse::<AccountId>().unwrap());
+        assert_eq!(src.amount.0, 1000);
+        assert_eq!(src.timelocks.deployed_at, 7);
+        
+        let dst = contract._create_immutables_from_orders(&order, false, 7);
+        assert_eq!(dst.order_hash, src.order_hash);
+        assert_eq!(dst.hashlock, src.hashlock);
+        assert_eq!(dst.maker, accounts(1));
+        assert_eq!(dst.taker, src.taker);
+        assert_eq!(dst.token, "token2.near".parse::<AccountId>().unwrap());
+        assert_eq!(dst.amount.0, 2100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Orders do not cross")]
+    fn test_initiate_rejects_orders_that_do_not_cross() {
+        setup_with_orders(create_test_order(accounts(1), 1), create_test_order(accounts(2), 2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Dst order gives too little")]
+    fn test_initiate_rejects_dst_order_short_of_src_ask() {
+        let mut dst_order = create_dst_order(accounts(2), 2);
+        dst_order.making_amount = U128(1900);
+        setup_with_orders(create_test_order(accounts(1), 1), dst_order);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only order resolver can deploy")]
+    fn test_dst_deploy_requires_order_resolver() {
+        let (mut contract, order_hash) = setup();
+        set_promise_results(vec![PromiseResult::Successful(b"\"src-escrow.factory.near\"".to_vec())]);
+        contract.on_src_escrow_created(order_hash, test_immutables(&contract, order_hash), accounts(0), U128(NEAR));
+        
+        testing_env!(get_context(accounts(4), 0));
+        contract.deploy_dst_escrow(order_hash);
+    }
+
+    #[test]
+    fn test_failed_legs_are_retried() {
+        let (mut contract, order_hash) = setup();
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(0), 500));
+        contract.execute_cross_chain_fill(order_hash, "secret".to_string());
+        let order = contract.get_cross_chain_order(order_hash).unwrap();
+        assert_eq!((order.src_leg, order.dst_leg), (LegStatus::Withdrawing, LegStatus::Withdrawing));
+        
+        // The src withdrawal lands but forwarding fails; the dst withdrawal fails outright
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.on_leg_withdrawn(order_hash, true));
+        set_promise_results(vec![PromiseResult::Failed]);
+        assert!(!contract.on_src_funds_forwarded(order_hash, U128(1000)));
+        assert!(!contract.on_leg_withdrawn(order_hash, false));
+        let order = contract.get_cross_chain_order(order_hash).unwrap();
+        assert_eq!((order.src_leg, order.dst_leg), (LegStatus::Withdrawn, LegStatus::Pending));
+        assert_eq!(order.status, CrossChainStatus::SecretRevealed);
+        
+        // A retry forwards the held src funds and withdraws dst again
+        testing_env!(get_context(accounts(0), 600));
+        contract.execute_cross_chain_fill(order_hash, "secret".to_string());
+        let order = contract.get_cross_chain_order(order_hash).unwrap();
+        assert_eq!((order.src_leg, order.dst_leg), (LegStatus::Forwarding, LegStatus::Withdrawing));
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_src_funds_forwarded(order_hash, U128(1000));
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().status, CrossChainStatus::SecretRevealed);
+        contract.on_leg_withdrawn(order_hash, false);
+        assert_eq!(contract.get_cross_chain_order(order_hash).unwrap().status, CrossChainStatus::Withdrawn);
+    }
+
+    fn setup_with_spread() -> (FusionResolver, [u8; 32]) {
+        // The counter-order only asks for 900 of the 1000 the src maker gives
+        let mut dst_order = create_dst_order(accounts(2), 2);
+        dst_order.taking_amount = U128(900);
+        let (mut contract, order_hash) = setup_with_orders(create_test_order(accounts(1), 1), dst_order);
+        deploy_escrows(&mut contract, order_hash);
+        
+        testing_env!(get_context(accounts(0), 500));
+        contract.execute_cross_chain_fill(order_hash, "secret".to_string());
+        (contract, order_hash)
+    }
+
+    fn setup_treasury(contract: &mut FusionResolver) {
+        testing_env!(get_context("resolver.near".parse().unwrap(), 0));
+        contract.set_treasury(accounts(5));
+        contract.add_treasury_operator(accounts(3));
+        testing_env!(get_context(accounts(3), 0));
+    }
+
+    #[test]
+    fn test_spread_realised_as_profit() {
+        let (mut contract, order_hash) = setup_with_spread();
+        let token: AccountId = "token1.near".parse().unwrap();
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_leg_withdrawn(order_hash, true);
+        assert_eq!(contract.get_inventory(), vec![(token.clone(), U128(100))]);
+        // Nothing is realised while the dst leg is still open
+        assert_eq!(contract.get_realised_pnl(token.clone()).0, 0);
+        assert_eq!(contract.get_withdrawable_profit(token.clone()).0, 0);
+        
+        contract.on_src_funds_forwarded(order_hash, U128(900));
+        contract.on_leg_withdrawn(order_hash, false);
+        
+        let flows = contract.get_order_flows(order_hash);
+        assert_eq!(flows.len(), 1);
+        assert_eq!((flows[0].inflow.0, flows[0].outflow.0), (1000, 900));
+        assert_eq!(contract.get_realised_pnl(token.clone()).0, 100);
+        assert_eq!(contract.get_withdrawable_profit(token).0, 100);
+    }
+
+    #[test]
+    fn test_failed_forward_returns_funds_to_inventory() {
+        let (mut contract, order_hash) = setup_with_spread();
+        let token: AccountId = "token1.near".parse().unwrap();
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_leg_withdrawn(order_hash, true);
+        set_promise_results(vec![PromiseResult::Failed]);
+        contract.on_src_funds_forwarded(order_hash, U128(900));
+        
+        assert_eq!(contract.get_inventory(), vec![(token, U128(1000))]);
+        assert_eq!(contract.get_order_flows(order_hash)[0].outflow.0, 0);
+    }
+
+    #[test]
+    fn test_owed_funds_are_not_withdrawable() {
+        let (mut contract, order_hash) = setup_with_spread();
+        let token: AccountId = "token1.near".parse().unwrap();
+        
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_leg_withdrawn(order_hash, true);
+        set_promise_results(vec![PromiseResult::Failed]);
+        contract.on_src_funds_forwarded(order_hash, U128(900));
+        
+        // Profit realised elsewhere cannot be paid out of the 900 owed to the dst maker
+        contract.realised_pnl.insert(&token, &1000);
+        assert_eq!(contract.get_withdrawable_profit(token.clone()).0, 100);
+        
+        // Retrying the forward releases the debt along with the funds
+        testing_env!(get_context(accounts(0), 500));
+        contract.execute_cross_chain_fill(order_hash, "secret".to_string());
+        assert_eq!(contract.owed.get(&token), Some(0));
+        assert_eq!(contract.get_inventory(), vec![(token, U128(100))]);
+    }
+
+    #[test]
+    fn test_withdraw_profit_to_treasury() {
+        let (mut contract, order_hash) = setup_with_spread();
+        let token: AccountId = "token1.near".parse().unwrap();
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_leg_withdrawn(order_hash, true);
+        contract.on_src_funds_forwarded(order_hash, U128(900));
+        contract.on_leg_withdrawn(order_hash, false);
+        
+        setup_treasury(&mut contract);
+        contract.withdraw_profit(token.clone(), U128(60));
+        assert_eq!(contract.get_withdrawable_profit(token.clone()).0, 40);
+        assert_eq!(contract.get_inventory(), vec![(token.clone(), U128(40))]);
+        
+        // A failed transfer puts the profit back
+        set_promise_results(vec![PromiseResult::Failed]);
+        assert!(!contract.on_profit_withdrawn(token.clone(), U128(60)));
+        assert_eq!(contract.get_withdrawable_profit(token).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient profit")]
+    fn test_cannot_withdraw_unrealised_profit() {
+        let (mut contract, order_hash) = setup_with_spread();
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_leg_withdrawn(order_hash, true);
+        
+        setup_treasury(&mut contract);
+        contract.withdraw_profit("token1.near".parse().unwrap(), U128(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not authorized treasury operator")]
+    fn test_withdraw_profit_requires_role() {
+        let (mut contract, _) = setup_with_spread();
+        setup_treasury(&mut contract);
+        
+        // Resolver operators cannot move profit without the treasury role
+        testing_env!(get_context(accounts(0), 0));
+        contract.withdraw_profit("token1.near".parse().unwrap(), U128(1));
+    }
+}
\ No newline at end of file
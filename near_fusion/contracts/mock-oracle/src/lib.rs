@@ -0,0 +1,37 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::UnorderedMap;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault};
+
+/// Minimal price oracle for exercising `FusionOrder` predicates in tests.
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct MockOracle {
+    owner: AccountId,
+    values: UnorderedMap<String, U128>,
+}
+
+#[near_bindgen]
+impl MockOracle {
+    #[init]
+    pub fn new(owner: AccountId) -> Self {
+        Self {
+            owner,
+            values: UnorderedMap::new(b"v"),
+        }
+    }
+
+    pub fn set_value(&mut self, key: String, value: U128) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can set values");
+        self.values.insert(&key, &value);
+    }
+
+    pub fn remove_value(&mut self, key: String) {
+        assert_eq!(env::predecessor_account_id(), self.owner, "Only owner can set values");
+        self.values.remove(&key);
+    }
+
+    pub fn get_value(&self, key: String) -> Option<U128> {
+        self.values.get(&key)
+    }
+}
\ No newline at end of file
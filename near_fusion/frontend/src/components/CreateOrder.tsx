@@ -1,10 +1,17 @@
 import { useState } from 'react';
+import { Contract } from 'near-api-js';
 import { useNear } from '../contexts/NearContext';
-import { TOKENS } from '../config/near';
+import { CONTRACT_CONFIG, TOKENS } from '../config/near';
 import type { FusionOrder, AuctionDetails } from '../types/contracts';
 
+const CREATE_ORDER_GAS = '100000000000000';
+const ORDER_STORAGE_DEPOSIT = '100000000000000000000000'; // 0.1 NEAR
+const STORAGE_DEPOSIT_GAS = '30000000000000';
+const BALANCE_STORAGE_DEPOSIT = '10000000000000000000000'; // 0.01 NEAR
+const FT_TRANSFER_CALL_GAS = '100000000000000';
+
 const CreateOrder = () => {
-  const { fusionOrderContract, isSignedIn } = useNear();
+  const { account, fusionOrderContract, isSignedIn } = useNear();
   
   const [makerAsset, setMakerAsset] = useState(TOKENS[0].id);
   const [takerAsset, setTakerAsset] = useState(TOKENS[1].id);
@@ -28,12 +35,45 @@ const CreateOrder = () => {
 
     setLoading(true);
     try {
+      const maker = fusionOrderContract.account?.accountId || '';
+      
+      // create_order reserves the making amount from the maker's balance in the
+      // Fusion contract, so top that balance up with ft_transfer_call first
+      // @ts-ignore
+      const balance: string = await fusionOrderContract.get_balance({ account_id: maker, token: makerAsset });
+      const shortfall = BigInt(makingAmount) - BigInt(balance);
+      if (shortfall > 0n) {
+        // A deposit opening a new balance is paid from prepaid storage
+        // @ts-ignore
+        const storageBalance: string = await fusionOrderContract.get_storage_balance({ account_id: maker });
+        if (BigInt(storageBalance) === 0n) {
+          // @ts-ignore
+          await fusionOrderContract.storage_deposit(
+            { account_id: maker },
+            STORAGE_DEPOSIT_GAS,
+            BALANCE_STORAGE_DEPOSIT // Unused storage can be withdrawn
+          );
+        }
+        
+        const makerToken = new Contract(account, makerAsset, {
+          viewMethods: [],
+          changeMethods: ['ft_transfer_call'],
+          useLocalViewExecution: false
+        });
+        // @ts-ignore
+        await makerToken.ft_transfer_call(
+          { receiver_id: CONTRACT_CONFIG.contracts.fusionOrder, amount: shortfall.toString(), msg: '' },
+          FT_TRANSFER_CALL_GAS,
+          '1' // NEP-141 requires exactly one yoctoNEAR
+        );
+      }
+      
       const salt = new Uint8Array(32);
       crypto.getRandomValues(salt);
       
       const order: FusionOrder = {
         order_hash: Array(32).fill(0).join(''), // Will be computed by contract
-        maker: fusionOrderContract.account?.accountId || '',
+        maker,
         resolver: undefined,
         maker_asset: makerAsset,
         taker_asset: takerAsset,
@@ -57,10 +97,11 @@ const CreateOrder = () => {
       }
 
       // @ts-ignore
-      await fusionOrderContract.create_order({
-        order,
-        auction,
-      });
+      await fusionOrderContract.create_order(
+        { order, auction },
+        CREATE_ORDER_GAS,
+        ORDER_STORAGE_DEPOSIT // Unused storage deposit is refunded
+      );
 
       alert('Order created successfully!');
       
@@ -189,6 +230,12 @@ const CreateOrder = () => {
           )}
         </div>
 
+        <p className="text-sm text-gray-600">
+          The amount you sell is first deposited into the Fusion contract, which
+          holds it until the order is filled or cancelled. Cancelled amounts stay
+          in your Fusion balance until you withdraw them.
+        </p>
+
         <button
           onClick={handleCreateOrder}
           disabled={loading || !isSignedIn}
@@ -53,8 +53,8 @@ export const NearProvider: React.FC<{ children: React.ReactNode }> = ({ children
         
         // Initialize contracts
         const fusionOrder = new Contract(account, CONTRACT_CONFIG.contracts.fusionOrder, {
-          viewMethods: ['get_order', 'get_remaining_amounts'],
-          changeMethods: ['create_order', 'fill_order', 'cancel_order', 'add_resolver', 'remove_resolver'],
+          viewMethods: ['get_order', 'get_remaining_amounts', 'get_balance', 'get_storage_balance'],
+          changeMethods: ['create_order', 'fill_order', 'cancel_order', 'storage_deposit', 'add_resolver', 'remove_resolver'],
           useLocalViewExecution: false
         });
         setFusionOrderContract(fusionOrder);
@@ -8,6 +8,28 @@ export interface FusionOrder {
   taking_amount: string;
   maker_traits: number;
   salt: string;
+  integrator_fee?: IntegratorFee;
+  exclusivity_end?: number;
+  predicate?: Predicate;
+  expiry?: number;
+}
+
+export type Condition =
+  | { type: 'after'; timestamp: number }
+  | { type: 'before'; timestamp: number }
+  | { type: 'nonce_equals'; nonce: number }
+  | { type: 'oracle_above'; oracle: string; key: string; value: string }
+  | { type: 'oracle_below'; oracle: string; key: string; value: string }
+  | { type: 'view_equals'; contract_id: string; method_name: string; args: string; expected: string };
+
+export interface Predicate {
+  type: 'and' | 'or';
+  conditions: Condition[];
+}
+
+export interface IntegratorFee {
+  receiver: string;
+  basis_points: number;
 }
 
 export interface AuctionDetails {
@@ -17,6 +39,18 @@ export interface AuctionDetails {
   points: AuctionPoint[];
 }
 
+export interface TwapSchedule {
+  start_time: number;
+  slice_count: number;
+  interval: number;
+}
+
+export interface RecurringSchedule {
+  start_time: number;
+  period: number;
+  amount_per_period: string;
+}
+
 export interface AuctionPoint {
   delay: number;
   coefficient: number;
@@ -28,6 +62,17 @@ export interface OrderState {
   filled_taking_amount: string;
   is_cancelled: boolean;
   auction?: AuctionDetails;
+  settlement?: OrderSettlement;
+  twap?: TwapSchedule;
+  recurring?: RecurringSchedule;
+}
+
+export interface OrderSettlement {
+  reason: 'filled' | 'cancelled';
+  filled_making_amount: string;
+  filled_taking_amount: string;
+  released_making_amount: string;
+  settled_at: number;
 }
 
 export interface Immutables {
@@ -84,21 +84,21 @@ pub struct Immutables {
 impl Immutables {
     pub fn hash(&self) -> [u8; 32] {
         let mut hasher = Keccak256::new();
-        hasher.update(&self.order_hash);
-        hasher.update(&self.hashlock);
+        hasher.update(self.order_hash);
+        hasher.update(self.hashlock);
         hasher.update(self.maker.as_bytes());
         hasher.update(self.taker.as_bytes());
         hasher.update(self.token.as_bytes());
-        hasher.update(&self.amount.0.to_le_bytes());
-        hasher.update(&self.safety_deposit.0.to_le_bytes());
-        hasher.update(&self.timelocks.src_withdrawal.to_le_bytes());
-        hasher.update(&self.timelocks.src_public_withdrawal.to_le_bytes());
-        hasher.update(&self.timelocks.src_cancellation.to_le_bytes());
-        hasher.update(&self.timelocks.src_public_cancellation.to_le_bytes());
-        hasher.update(&self.timelocks.dst_withdrawal.to_le_bytes());
-        hasher.update(&self.timelocks.dst_public_withdrawal.to_le_bytes());
-        hasher.update(&self.timelocks.dst_cancellation.to_le_bytes());
-        hasher.update(&self.timelocks.deployed_at.to_le_bytes());
+        hasher.update(self.amount.0.to_le_bytes());
+        hasher.update(self.safety_deposit.0.to_le_bytes());
+        hasher.update(self.timelocks.src_withdrawal.to_le_bytes());
+        hasher.update(self.timelocks.src_public_withdrawal.to_le_bytes());
+        hasher.update(self.timelocks.src_cancellation.to_le_bytes());
+        hasher.update(self.timelocks.src_public_cancellation.to_le_bytes());
+        hasher.update(self.timelocks.dst_withdrawal.to_le_bytes());
+        hasher.update(self.timelocks.dst_public_withdrawal.to_le_bytes());
+        hasher.update(self.timelocks.dst_cancellation.to_le_bytes());
+        hasher.update(self.timelocks.deployed_at.to_le_bytes());
         hasher.finalize().into()
     }
 }
@@ -122,6 +122,46 @@ pub struct FusionOrder {
     pub taking_amount: U128,
     pub maker_traits: u64,
     pub salt: [u8; 32],
+    #[serde(default)]
+    pub integrator_fee: Option<IntegratorFee>,
+    /// Until this time only `resolver` may fill; afterwards any whitelisted resolver can.
+    #[serde(default)]
+    pub exclusivity_end: Option<u64>,
+    #[serde(default)]
+    pub predicate: Option<Predicate>,
+    /// Time after which the order can no longer be filled.
+    #[serde(default)]
+    pub expiry: Option<u64>,
+}
+
+/// On-chain conditions that must hold for an order to be filled.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde", tag = "type", rename_all = "snake_case")]
+pub enum Predicate {
+    And { conditions: Vec<Condition> },
+    Or { conditions: Vec<Condition> },
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde", tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// Block timestamp is at or after `timestamp`.
+    After { timestamp: u64 },
+    /// Block timestamp is before `timestamp`.
+    Before { timestamp: u64 },
+    /// The maker has not bumped their nonce since the order was signed.
+    NonceEquals { nonce: u64 },
+    /// Value stored in `oracle` under `key` is at or above `value`.
+    OracleAbove { oracle: AccountId, key: String, value: U128 },
+    /// Value stored in `oracle` under `key` is below `value`.
+    OracleBelow { oracle: AccountId, key: String, value: U128 },
+    /// View `method_name` on `contract_id` returns JSON equal to `expected`.
+    ViewEquals {
+        contract_id: AccountId,
+        method_name: String,
+        args: String,
+        expected: String,
+    },
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
@@ -140,6 +180,14 @@ pub struct AuctionPoint {
     pub coefficient: u32,
 }
 
+/// Cut of the making amount that goes to the frontend or wallet that routed the order.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntegratorFee {
+    pub receiver: AccountId,
+    pub basis_points: u32,
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct ResolverFee {
@@ -150,20 +198,67 @@ pub struct ResolverFee {
 impl FusionOrder {
     pub fn hash(&self) -> [u8; 32] {
         let mut hasher = Keccak256::new();
-        hasher.update(&self.order_hash);
+        hasher.update(self.order_hash);
         hasher.update(self.maker.as_bytes());
         if let Some(resolver) = &self.resolver {
             hasher.update(resolver.as_bytes());
         }
         hasher.update(self.maker_asset.as_bytes());
         hasher.update(self.taker_asset.as_bytes());
-        hasher.update(&self.making_amount.0.to_le_bytes());
-        hasher.update(&self.taking_amount.0.to_le_bytes());
-        hasher.update(&self.maker_traits.to_le_bytes());
-        hasher.update(&self.salt);
+        hasher.update(self.making_amount.0.to_le_bytes());
+        hasher.update(self.taking_amount.0.to_le_bytes());
+        hasher.update(self.maker_traits.to_le_bytes());
+        hasher.update(self.salt);
+        if let Some(integrator_fee) = &self.integrator_fee {
+            hasher.update(b"integrator_fee");
+            hasher.update(integrator_fee.receiver.as_bytes());
+            hasher.update(integrator_fee.basis_points.to_le_bytes());
+        }
+        if let Some(exclusivity_end) = self.exclusivity_end {
+            hasher.update(b"exclusivity_end");
+            hasher.update(exclusivity_end.to_le_bytes());
+        }
+        if let Some(predicate) = &self.predicate {
+            hasher.update(b"predicate");
+            hasher.update(serde_json::to_vec(predicate).unwrap());
+        }
+        if let Some(expiry) = self.expiry {
+            hasher.update(b"expiry");
+            hasher.update(expiry.to_le_bytes());
+        }
         hasher.finalize().into()
     }
 
+    /// Taking amount owed for `making_amount` on top of what is already filled.
+    /// Computed on the cumulative totals and rounded up, so however the order is
+    /// split the maker never receives less than its overall price.
+    pub fn taking_amount_for(&self, filled_making_amount: u128, filled_taking_amount: u128, making_amount: u128) -> u128 {
+        let total = mul_div(
+            filled_making_amount + making_amount,
+            self.taking_amount.0,
+            self.making_amount.0,
+            true,
+        );
+        total.saturating_sub(filled_taking_amount)
+    }
+
+    /// Making amount released for `taking_amount` on top of what is already filled.
+    /// Computed on the cumulative totals and rounded down, in the maker's favour.
+    pub fn making_amount_for(&self, filled_making_amount: u128, filled_taking_amount: u128, taking_amount: u128) -> u128 {
+        let total = mul_div(
+            filled_taking_amount + taking_amount,
+            self.making_amount.0,
+            self.taking_amount.0,
+            false,
+        );
+        total.saturating_sub(filled_making_amount)
+    }
+
+    /// Whether the cumulative fill totals pay at least the order's price.
+    pub fn is_price_respected(&self, filled_making_amount: u128, filled_taking_amount: u128) -> bool {
+        full_mul(filled_taking_amount, self.making_amount.0) >= full_mul(filled_making_amount, self.taking_amount.0)
+    }
+
     pub fn is_valid_partial_fill(&self, filled_making_amount: u128, filled_taking_amount: u128) -> bool {
         if filled_making_amount == 0 || filled_taking_amount == 0 {
             return false;
@@ -197,4 +292,46 @@ impl AuctionDetails {
         
         current_bump
     }
+}
+
+/// Computes `a * b / denominator` with a 256-bit intermediate product.
+pub fn mul_div(a: u128, b: u128, denominator: u128, round_up: bool) -> u128 {
+    assert!(denominator > 0, "Division by zero");
+    let (hi, lo) = full_mul(a, b);
+
+    let mut quotient: u128 = 0;
+    let mut remainder: u128 = 0;
+    for i in (0..256).rev() {
+        let bit = if i >= 128 { (hi >> (i - 128)) & 1 } else { (lo >> i) & 1 };
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | bit;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            assert!(i < 128, "Amount overflow");
+            quotient |= 1 << i;
+        }
+    }
+
+    if round_up && remainder > 0 {
+        quotient.checked_add(1).expect("Amount overflow")
+    } else {
+        quotient
+    }
+}
+
+/// Full 256-bit product of two `u128`s as `(high, low)` halves.
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let mask = u64::MAX as u128;
+    let (a_lo, a_hi) = (a & mask, a >> 64);
+    let (b_lo, b_hi) = (b & mask, b >> 64);
+
+    let ll = a_lo * b_lo;
+    let lh = a_lo * b_hi;
+    let hl = a_hi * b_lo;
+    let hh = a_hi * b_hi;
+
+    let mid = (ll >> 64) + (lh & mask) + (hl & mask);
+    let lo = (ll & mask) | (mid << 64);
+    let hi = hh + (lh >> 64) + (hl >> 64) + (mid >> 64);
+    (hi, lo)
 }
\ No newline at end of file